@@ -1,27 +1,87 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use env_logger::fmt::style::{AnsiColor, Color, Style};
 use std::io::Write;
 use tao::{
-    event::{Event, KeyEvent, WindowEvent},
-    event_loop::{EventLoop, EventLoopBuilder},
+    event::{Event, KeyEvent, StartCause, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy},
     window::WindowId,
 };
 
 use crate::{
-    AppConfig, StdRpcClient,
-    app::{user_event::UserEvent, window::AppWindow},
-    capscreen::enumerate::enumerate_all_windows,
+    AppConfig, RpcTransport, StdRpcClient,
+    app::{
+        commands::{self, Capabilities, CaptureOutcome, CaptureResult, ExitReason, Exiting},
+        rpc::RpcDispatcher,
+        user_event::UserEvent,
+        watchdog,
+        window::AppWindow,
+    },
+    capscreen::{CaptureBackend, CaptureError},
+    exit_code::{EXIT_FAILURE, EXIT_USAGE},
+    stdio::Connection,
 };
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+/// 退出前等待宿主读取剩余消息的最长时间
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 创建应用失败的原因，由调用方打印并以对应的退出码退出
+#[derive(Debug)]
+pub enum AppError {
+    /// --capture-from/--windows-from 指定的文件不存在或无法解析
+    LoadCapture(CaptureError),
+    /// --rpc 指定的通道无法打开
+    OpenTransport(RpcTransport, std::io::Error),
+}
+
+impl AppError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::LoadCapture(_) => EXIT_USAGE,
+            AppError::OpenTransport(..) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::LoadCapture(e) => write!(f, "failed to load capture from file: {:?}", e),
+            AppError::OpenTransport(transport, e) => {
+                write!(f, "failed to open rpc transport {:?}: {}", transport, e)
+            }
+        }
+    }
+}
+
 pub struct App {
     windows: HashMap<WindowId, AppWindow>,
     event_loop: EventLoop<UserEvent>,
+    rpc: RpcDispatcher,
+    idle_timeout: Option<Duration>,
+    /// 最后一次用户操作或收到 RPC 请求的时间
+    last_activity: Instant,
+    /// 最近一次保存、复制或失败的结果，退出时没有结果为取消
+    capture_result: Option<CaptureResult>,
 }
 
 impl App {
     /// 将标准错误接口的输出用作输出，标准输出的接口的输出用作STDIO
-    pub fn new(config: Option<AppConfig>) -> Self {
+    pub fn new(config: Option<AppConfig>) -> Result<Self, AppError> {
+        let config = config.unwrap_or_default();
+        let backend = config.capture_backend().map_err(AppError::LoadCapture)?;
+        Self::with_backend(Some(config), backend)
+    }
+
+    /// 使用指定的截图后端创建应用，嵌入方和测试可以注入自己的实现
+    pub fn with_backend(
+        config: Option<AppConfig>,
+        backend: Box<dyn CaptureBackend>,
+    ) -> Result<Self, AppError> {
+        let backend: Arc<dyn CaptureBackend> = Arc::from(backend);
         let config = config.unwrap_or_default();
         let mut logger_builder = env_logger::builder();
    
@@ -53,34 +113,68 @@ impl App {
             )
         });
         logger_builder.init();
-        log::error!("App::new {:?}", config);
+        log::error!("App::new {:?}, capture backend: {}", config, backend.name());
         let start_time = Instant::now();
         let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
         let proxy = event_loop.create_proxy();
 
-        StdRpcClient::init(
-            move |req| {
+        // 请求在监听线程中按方法表校验参数，再交给 GUI 线程处理，由 RpcDispatcher 在得到结果后回复
+        let registry = commands::registry(move |command| {
+            // 发送失败时 responder 随事件一起被丢弃，自动回复错误
+            if let Err(e) = proxy.send_event(UserEvent::Rpc(command)) {
+                log::error!("Failed to send event to GUI loop: {}", e);
+            }
+        });
+        let capabilities = Capabilities::new(&registry);
+        let connection = Connection::open(config.rpc_transport())
+            .map_err(|e| AppError::OpenTransport(config.rpc_transport().clone(), e))?
+            .with_framing(config.rpc_framing());
+        let exit_on_close = connection.exit_on_close;
+        let listener = StdRpcClient::init_with(
+            connection,
+            move |req, responder| {
                 log::error!("RpcMessage: {:?}", req);
-                if let Err(e) = proxy.send_event(UserEvent::RpcMessage(req)) {
-                    log::error!("Failed to send event to GUI loop: {}", e);
-                }
-                Ok(serde_json::Value::Null)
+                registry.handle(req, responder);
             },
             move |notif| {
                 log::error!("RpcNotification: {:?}", notif);
             },
         );
+        // 宿主崩溃或关闭通道后退出，避免界面一直留在屏幕上
+        if exit_on_close {
+            let proxy = event_loop.create_proxy();
+            std::thread::spawn(move || {
+                let _ = listener.join();
+                send_exit(&proxy, ExitReason::HostDisconnected);
+            });
+        }
+        // 没有 --parent-pid 时只在有宿主连接时监视父进程，避免脚本中 `quickcap &` 随脚本结束退出
+        if config.parent_pid().is_some() || exit_on_close {
+            let proxy = event_loop.create_proxy();
+            watchdog::watch_parent(config.parent_pid(), move || {
+                send_exit(&proxy, ExitReason::ParentExited);
+            });
+        }
+
+        // 延时截图，给用户时间打开菜单等界面
+        if !config.delay().is_zero() {
+            log::error!("delay capture: {:?}", config.delay());
+            std::thread::sleep(config.delay());
+        }
 
         // 在创建所有窗口之前，统一枚举一次所有窗口，避免重复执行
         let start_enumerate_time = Instant::now();
-        let all_windows = Arc::new(enumerate_all_windows().unwrap_or_default());
+        let all_windows = Arc::new(backend.enumerate_windows().unwrap_or_else(|e| {
+            log::error!("enumerate windows failed: {:?}", e);
+            vec![]
+        }));
         log::error!(
             "enumerate all windows time: {:?}, count: {}",
             start_enumerate_time.elapsed(),
             all_windows.len()
         );
 
-        // Windows和Macos的逻辑并不一致，Windows是用虚拟桌面
+        // Windows和Macos的逻辑并不一致，Windows和Linux(X11)是用虚拟桌面
         #[cfg(target_os = "macos")]
         let windows = {
             let monitors = event_loop.available_monitors().collect::<Vec<_>>();
@@ -88,26 +182,47 @@ impl App {
                 .into_iter()
                 .map(|monitor| {
                     log::error!("Monitor: {:?}", monitor);
-                    AppWindow::new(monitor, &event_loop, &config, Arc::clone(&all_windows))
+                    AppWindow::new(
+                        monitor,
+                        &event_loop,
+                        &config,
+                        Arc::clone(&all_windows),
+                        Arc::clone(&backend),
+                    )
                 })
                 .map(|window| (window.window.id(), window))
                 .collect();
             windows
         };
-        #[cfg(target_os = "windows")]
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
         let windows = {
             let monitor = event_loop.primary_monitor().unwrap();
-            // 保留一个窗口，在windows中monitor并不是必要参数，但macos先开发，所以保留一个传参
+            // 保留一个窗口，在windows和linux中monitor并不是必要参数，但macos先开发，所以保留一个传参
             // 后续优化点: 添加AppWindowBuilder，根据不同的操作系统创建不同的AppWindow
-            let window = AppWindow::new(monitor, &event_loop, &config, Arc::clone(&all_windows));
+            let window = AppWindow::new(
+                monitor,
+                &event_loop,
+                &config,
+                Arc::clone(&all_windows),
+                Arc::clone(&backend),
+            );
             HashMap::from([(window.window.id(), window)])
         };
         log::error!("windows time: {:?}", start_time.elapsed());
 
-        Self {
+        Ok(Self {
             windows,
             event_loop,
-        }
+            rpc: RpcDispatcher::new(
+                backend,
+                all_windows,
+                capabilities,
+                config.wait_initialize(),
+            ),
+            idle_timeout: config.idle_timeout(),
+            last_activity: Instant::now(),
+            capture_result: None,
+        })
     }
 
     /// 运行应用，创建并运行事件循环
@@ -115,9 +230,23 @@ impl App {
     pub fn run(mut self) -> ! {
         log::error!("App::run");
         self.event_loop.run(move |event, _, control_flow| {
-            *control_flow = tao::event_loop::ControlFlow::Wait;
-            match event {
-                Event::NewEvents(tao::event::StartCause::Init) => {}
+            if matches!(
+                event,
+                Event::WindowEvent { .. }
+                    | Event::DeviceEvent { .. }
+                    | Event::UserEvent(UserEvent::Rpc(_) | UserEvent::RpcResult { .. })
+            ) {
+                self.last_activity = Instant::now();
+            }
+            *control_flow = match self.idle_timeout {
+                Some(timeout) => ControlFlow::WaitUntil(self.last_activity + timeout),
+                None => ControlFlow::Wait,
+            };
+            let exit_reason = match event {
+                Event::NewEvents(StartCause::ResumeTimeReached { .. }) => self
+                    .idle_timeout
+                    .filter(|timeout| self.last_activity.elapsed() >= *timeout)
+                    .map(|_| ExitReason::IdleTimeout),
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     ..
@@ -133,13 +262,15 @@ impl App {
                             ..
                         },
                     ..
+                } => {
+                    log::error!("WindowEvent::CloseRequested");
+                    Some(ExitReason::Closed)
                 }
-                | Event::UserEvent(UserEvent::Exit) => {
-                    if !self.windows.is_empty() {
-                        log::error!("WindowEvent::CloseRequested");
-                        self.windows.clear();
-                        *control_flow = tao::event_loop::ControlFlow::Exit;
-                    }
+                Event::UserEvent(UserEvent::Exit(reason)) => Some(reason),
+                Event::UserEvent(UserEvent::CaptureResult(result)) => {
+                    log::error!("CaptureResult: {:?}", result);
+                    self.capture_result = Some(result);
+                    None
                 }
                 Event::WindowEvent {
                     window_id,
@@ -149,12 +280,62 @@ impl App {
                     if let Some(window) = self.windows.get_mut(&window_id) {
                         window.window.set_focus();
                     }
+                    None
+                }
+                Event::UserEvent(UserEvent::Rpc(command)) => {
+                    log::error!("RpcCommand: {:?}", command);
+                    self.rpc
+                        .handle_command(command, &self.windows)
+                        .then_some(ExitReason::ExitRequest)
+                }
+                Event::UserEvent(UserEvent::RpcResult { id, result }) => {
+                    self.rpc.handle_result(id, result);
+                    None
                 }
-                Event::UserEvent(UserEvent::RpcMessage(req)) => {
-                    log::error!("RpcMessage: {:?}", req);
+                Event::UserEvent(UserEvent::RunOnMainThread(task)) => {
+                    (task.0)();
+                    None
+                }
+                // 事件循环结束后进程立即退出，等待写线程写出 exiting 等最后的消息
+                Event::LoopDestroyed => {
+                    let client = StdRpcClient::global();
+                    if !client.flush(FLUSH_TIMEOUT) {
+                        log::error!("Failed to flush rpc messages: {:?}", client.writer_stats());
+                    }
+                    None
                 }
-                _ => {}
+                _ => None,
+            };
+
+            // 关闭所有窗口并退出事件循环，最后发送截图结果和退出原因，退出码与截图结果一致
+            if let Some(reason) = exit_reason
+                && !self.windows.is_empty()
+            {
+                log::error!("exit: {:?}", reason);
+                self.rpc.cancel_pending();
+                let result = self
+                    .capture_result
+                    .take()
+                    .unwrap_or_else(|| CaptureResult::new(CaptureOutcome::Cancelled, None));
+                let client = StdRpcClient::global();
+                client.send_notification(
+                    "capture_result",
+                    Some(serde_json::to_value(&result).unwrap()),
+                );
+                client.send_notification(
+                    "exiting",
+                    Some(serde_json::to_value(Exiting { reason }).unwrap()),
+                );
+                self.windows.clear();
+                *control_flow = ControlFlow::ExitWithCode(result.outcome.exit_code());
             }
         })
     }
 }
+
+/// 从其他线程请求退出，事件循环已经退出时忽略
+fn send_exit(proxy: &EventLoopProxy<UserEvent>, reason: ExitReason) {
+    if let Err(e) = proxy.send_event(UserEvent::Exit(reason)) {
+        log::error!("Failed to send exit event: {}", e);
+    }
+}
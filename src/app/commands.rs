@@ -0,0 +1,389 @@
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::config::{ImageOutput, SaveHandler},
+    capscreen::{
+        MonitorInfo,
+        encode::ImageFormat,
+        enumerate::{Rect, WindowInfo},
+    },
+    exit_code::{EXIT_CANCELLED, EXIT_FAILURE, EXIT_SUCCESS},
+    stdio::{Framing, NoParams, RpcRegistry, TypedResponder, client::RpcError},
+};
+
+/// initialize 握手使用的协议版本，方法或通知不兼容时递增
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// 宿主在 initialize 中发送的信息
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct InitializeParams {
+    /// 宿主实现的协议版本，与 PROTOCOL_VERSION 不一致时握手失败
+    pub protocol_version: u32,
+    pub client_info: Option<PeerInfo>,
+    /// 只接收这些通知，省略时接收全部通知
+    pub notifications: Option<Vec<String>>,
+    /// initialize 的回复之后发送消息使用的分帧方式
+    pub framing: Option<Framing>,
+    /// 保存时由谁选择位置，省略时使用 --save-handler
+    pub save_handler: Option<SaveHandler>,
+    /// capture_result 中图片的传递方式，省略时使用 --image-output
+    pub image_output: Option<ImageOutput>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PeerInfo {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct InitializeResult {
+    pub protocol_version: u32,
+    pub server_info: PeerInfo,
+    /// std::env::consts::OS，如 linux、macos、windows
+    pub platform: String,
+    /// 截图后端的名称
+    pub backend: String,
+    pub capabilities: Capabilities,
+}
+
+/// 当前版本支持的格式、方法和通知
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Capabilities {
+    pub formats: Vec<ImageFormat>,
+    pub framings: Vec<Framing>,
+    pub methods: Vec<String>,
+    pub notifications: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn new(registry: &RpcRegistry) -> Self {
+        Self {
+            formats: ImageFormat::value_variants().to_vec(),
+            framings: Framing::value_variants().to_vec(),
+            methods: registry.method_names(),
+            notifications: registry.notification_names(),
+        }
+    }
+}
+
+/// 标注工具，与工具栏的 data-shape 一致
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Tool {
+    Rect,
+    Circle,
+    Arrow,
+    Path,
+    Mosaic,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ToolParams {
+    /// null 为取消当前工具
+    pub tool: Option<Tool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SaveResult {
+    /// 保存的路径，用户取消保存对话框或 save_handler 为 data 时为 null
+    pub path: Option<String>,
+}
+
+/// save_handler 为 host 时调用宿主的 `chooseSavePath` 方法的参数
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ChooseSavePathParams {
+    /// 按 filename_template 生成的文件名，包含扩展名
+    pub file_name: String,
+    /// save_dir，未设置时为下载目录
+    pub directory: String,
+    pub format: ImageFormat,
+}
+
+/// `chooseSavePath` 的结果
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ChooseSavePathResult {
+    /// quickcap 写入的路径，null 为取消，蒙层保持打开
+    pub path: Option<String>,
+}
+
+/// 按配置的格式和质量编码后的图片
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct EncodedImage {
+    pub format: ImageFormat,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// base64，image_output 为 inline 和 capture_data 中
+    pub data: Option<String>,
+    /// 临时文件，image_output 为 file 时，由宿主读取后删除
+    pub path: Option<String>,
+}
+
+impl EncodedImage {
+    /// 还没有 data 和 path 的图片
+    pub fn new(format: ImageFormat, width: u32, height: u32) -> Self {
+        Self {
+            format,
+            mime_type: format.mime_type().to_string(),
+            width,
+            height,
+            data: None,
+            path: None,
+        }
+    }
+}
+
+/// `capture_data` 通知，save_handler 为 data 时代替写入文件
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CaptureData {
+    /// 按 filename_template 生成的文件名，包含扩展名
+    pub file_name: String,
+    #[serde(flatten)]
+    pub image: EncodedImage,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CopyResult {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// `save_image_to_folder` 通知
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SavedImage {
+    pub path: String,
+}
+
+/// `webview_error` 通知
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WebviewError {
+    pub error: String,
+}
+
+/// 截图的最终结果
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureOutcome {
+    Saved,
+    /// 复制到剪贴板，post_capture 同时保存时 path 不为 null
+    Copied,
+    /// image_output 不为 none 时确认选区，图片只通过 image 交给宿主
+    Delivered,
+    /// 没有保存或复制就关闭了蒙层
+    Cancelled,
+    /// 截图、编码、保存或复制失败
+    Error,
+}
+
+impl CaptureOutcome {
+    /// 进程的退出码
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CaptureOutcome::Saved | CaptureOutcome::Copied | CaptureOutcome::Delivered => {
+                EXIT_SUCCESS
+            }
+            CaptureOutcome::Cancelled => EXIT_CANCELLED,
+            CaptureOutcome::Error => EXIT_FAILURE,
+        }
+    }
+}
+
+/// 选区在两种坐标系下的位置
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Selection {
+    /// 物理像素，Windows 和 Linux 上与 getWindows 的坐标相同
+    pub physical: Rect,
+    /// 逻辑像素(物理像素 / scale_factor)，macOS 上与 getWindows 的坐标相同
+    pub logical: Rect,
+    pub scale_factor: f64,
+}
+
+/// `capture_result` 通知，退出前在 `exiting` 之前发送一次
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CaptureResult {
+    pub outcome: CaptureOutcome,
+    /// 没有选区时为 null
+    pub selection: Option<Selection>,
+    /// 选区中心所在的显示器，与 getMonitors 的 id 相同
+    pub monitor: Option<u32>,
+    /// 选区中心下最上层的窗口
+    pub window: Option<WindowInfo>,
+    /// 保存的文件路径
+    pub path: Option<String>,
+    /// 选区上的标注数量
+    pub annotations: usize,
+    /// outcome 为 error 时的错误信息
+    pub error: Option<String>,
+    /// image_output 不为 none 时保存、复制或确认的图片
+    pub image: Option<EncodedImage>,
+}
+
+impl CaptureResult {
+    /// 没有选区等信息的结果，用于取消和截图失败
+    pub fn new(outcome: CaptureOutcome, error: Option<String>) -> Self {
+        Self {
+            outcome,
+            selection: None,
+            monitor: None,
+            window: None,
+            path: None,
+            annotations: 0,
+            error,
+            image: None,
+        }
+    }
+}
+
+/// quickcap 退出的原因
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    /// 界面关闭，用户按 Escape 或保存、复制后关闭
+    Closed,
+    /// 宿主调用 exit
+    ExitRequest,
+    /// RPC 通道断开，宿主已经退出
+    HostDisconnected,
+    /// 父进程退出
+    ParentExited,
+    /// 超过 idle_timeout 没有操作和 RPC 消息
+    IdleTimeout,
+}
+
+/// `exiting` 通知，退出前发送的最后一条消息
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Exiting {
+    pub reason: ExitReason,
+}
+
+/// 参数已经校验、需要在 GUI 线程处理的请求
+#[derive(Debug)]
+pub enum RpcCommand {
+    Initialize(InitializeParams, TypedResponder<InitializeResult>),
+    Exit(TypedResponder<()>),
+    GetWindows(TypedResponder<Vec<WindowInfo>>),
+    GetMonitors(TypedResponder<Vec<MonitorInfo>>),
+    /// 选区使用与 getWindows 相同的桌面坐标
+    SetSelection(Rect, TypedResponder<Rect>),
+    SetTool(ToolParams, TypedResponder<ToolParams>),
+    Save(TypedResponder<SaveResult>),
+    Copy(TypedResponder<CopyResult>),
+}
+
+impl RpcCommand {
+    /// 不处理请求，直接回复错误
+    pub fn reject(self, error: RpcError) {
+        match self {
+            RpcCommand::Initialize(_, responder) => responder.respond(Err(error)),
+            RpcCommand::Exit(responder) => responder.respond(Err(error)),
+            RpcCommand::GetWindows(responder) => responder.respond(Err(error)),
+            RpcCommand::GetMonitors(responder) => responder.respond(Err(error)),
+            RpcCommand::SetSelection(_, responder) => responder.respond(Err(error)),
+            RpcCommand::SetTool(_, responder) => responder.respond(Err(error)),
+            RpcCommand::Save(responder) => responder.respond(Err(error)),
+            RpcCommand::Copy(responder) => responder.respond(Err(error)),
+        }
+    }
+}
+
+/// 宿主可以调用的方法和会收到的通知，`send` 将请求转交给 GUI 线程
+pub fn registry(send: impl Fn(RpcCommand) + Clone + Send + Sync + 'static) -> RpcRegistry {
+    let mut registry = RpcRegistry::new();
+    registry
+        .method("initialize", forward(&send, RpcCommand::Initialize))
+        .method("exit", forward(&send, |_: NoParams, r| RpcCommand::Exit(r)))
+        .method(
+            "getWindows",
+            forward(&send, |_: NoParams, r| RpcCommand::GetWindows(r)),
+        )
+        .method(
+            "getMonitors",
+            forward(&send, |_: NoParams, r| RpcCommand::GetMonitors(r)),
+        )
+        .method("setSelection", forward(&send, RpcCommand::SetSelection))
+        .method("setTool", forward(&send, RpcCommand::SetTool))
+        .method("save", forward(&send, |_: NoParams, r| RpcCommand::Save(r)))
+        .method("copy", forward(&send, |_: NoParams, r| RpcCommand::Copy(r)))
+        .notification::<SavedImage>("save_image_to_folder")
+        .notification::<CopyResult>("copy_to_clipboard")
+        .notification::<WebviewError>("webview_error")
+        .notification::<CaptureData>("capture_data")
+        .notification::<CaptureResult>("capture_result")
+        .notification::<Exiting>("exiting");
+    registry
+}
+
+fn forward<P, R>(
+    send: &(impl Fn(RpcCommand) + Clone + Send + Sync + 'static),
+    command: fn(P, TypedResponder<R>) -> RpcCommand,
+) -> impl Fn(P, TypedResponder<R>) + Send + Sync + 'static
+where
+    P: 'static,
+    R: 'static,
+{
+    let send = send.clone();
+    move |params, responder| send(command(params, responder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_schema() {
+        let doc = registry(|_| {}).discover();
+        let methods = doc["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|method| method["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            vec![
+                "copy",
+                "exit",
+                "getMonitors",
+                "getWindows",
+                "initialize",
+                "save",
+                "setSelection",
+                "setTool"
+            ]
+        );
+        let schemas = &doc["components"]["schemas"];
+        assert!(schemas["Rect"].is_object());
+        assert!(schemas["WindowInfo"].is_object());
+        assert!(schemas["Tool"].is_object());
+    }
+
+    #[test]
+    fn test_capture_result() {
+        let result = CaptureResult::new(CaptureOutcome::Cancelled, None);
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["outcome"], "cancelled");
+        assert!(json["selection"].is_null());
+        assert_eq!(json["annotations"], 0);
+        assert!(json["image"].is_null());
+        assert_eq!(CaptureOutcome::Copied.exit_code(), EXIT_SUCCESS);
+        assert_eq!(CaptureOutcome::Delivered.exit_code(), EXIT_SUCCESS);
+        assert_eq!(CaptureOutcome::Error.exit_code(), EXIT_FAILURE);
+        assert_eq!(CaptureOutcome::Cancelled.exit_code(), EXIT_CANCELLED);
+
+        // capture_data 中图片的字段与文件名在同一层
+        let data = CaptureData {
+            file_name: "shot.jpg".to_string(),
+            image: EncodedImage {
+                data: Some("AAAA".to_string()),
+                ..EncodedImage::new(ImageFormat::Jpeg, 2, 1)
+            },
+        };
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["mime_type"], "image/jpeg");
+        assert_eq!(json["data"], "AAAA");
+        assert_eq!(json["file_name"], "shot.jpg");
+    }
+}
@@ -1,27 +1,324 @@
-#[derive(Debug)]
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use chrono::Local;
+use clap::{Parser, ValueEnum};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capscreen::{
+        self, CaptureBackend, CaptureError, FileBackend,
+        encode::{EncodeOptions, ImageFormat, PngCompression},
+    },
+    cli::Cli,
+    stdio::Framing,
+};
+
+use super::config_file::ConfigFile;
+
+/// 默认的文件名模板，chrono 的 strftime 格式
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "screenshot%Y%m%d%H%M%S";
+
+/// 蒙层打开后的初始模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureMode {
+    /// 拖拽选择区域，鼠标悬停时吸附窗口
+    #[default]
+    Region,
+    /// 只能选择窗口
+    Window,
+    /// 直接选中整个屏幕
+    Fullscreen,
+}
+
+impl CaptureMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaptureMode::Region => "region",
+            CaptureMode::Window => "window",
+            CaptureMode::Fullscreen => "fullscreen",
+        }
+    }
+}
+
+/// 确认选区(复制)后执行的动作
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostCaptureAction {
+    /// 复制到剪贴板
+    Copy,
+    /// 按 filename_template 直接保存到 save_dir，不弹出保存对话框
+    Save,
+}
+
+/// 保存(Ctrl/Cmd+S 或 save 方法)时由谁选择位置
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveHandler {
+    /// 系统的保存对话框
+    #[default]
+    Dialog,
+    /// 调用宿主的 chooseSavePath 方法，由宿主显示自己的对话框
+    Host,
+    /// 不写入文件，通过 capture_data 通知把编码后的图片发送给宿主
+    Data,
+}
+
+/// capture_result 中最终图片的传递方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageOutput {
+    /// 不传递图片
+    #[default]
+    None,
+    /// base64 放在 image.data 中
+    Inline,
+    /// 写入临时文件，路径放在 image.path 中，由宿主删除
+    File,
+}
+
+/// 原生控件(滚动条、输入框等)的配色，通过 CSS color-scheme 设置，不影响前端自绘的工具栏
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// 跟随系统
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::System => "system",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+}
+
+/// 与宿主通信的 JSON-RPC 通道，除 stdio 外都由宿主创建，quickcap 连接
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum RpcTransport {
+    /// 标准输入输出，每行一个 JSON
+    #[default]
+    Stdio,
+    /// `unix:PATH`，Unix 域套接字
+    Unix(PathBuf),
+    /// `tcp:PORT` 或 `tcp:HOST:PORT`，只允许回环地址，连接后发送 QUICKCAP_RPC_TOKEN 认证
+    Tcp(SocketAddr),
+    /// `pipe:NAME`，Windows 命名管道，如 `\\.\pipe\quickcap`
+    Pipe(String),
+    /// `--rpc-fd`，父进程传入的双向描述符，Windows 上为句柄
+    Fd(i32),
+}
+
+impl FromStr for RpcTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, value) = s.split_once(':').unwrap_or((s, ""));
+        match (scheme, value) {
+            ("stdio", "") => Ok(RpcTransport::Stdio),
+            ("unix", path) if !path.is_empty() => Ok(RpcTransport::Unix(PathBuf::from(path))),
+            ("tcp", addr) => {
+                let addr = match addr.parse::<u16>() {
+                    Ok(port) => SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+                    Err(_) => addr.parse::<SocketAddr>().map_err(|_| {
+                        format!("expected `tcp:PORT` or `tcp:HOST:PORT`, got `{}`", s)
+                    })?,
+                };
+                if !addr.ip().is_loopback() {
+                    return Err(format!(
+                        "tcp transport only accepts loopback addresses, got `{}`",
+                        addr
+                    ));
+                }
+                Ok(RpcTransport::Tcp(addr))
+            }
+            ("pipe", name) if !name.is_empty() => Ok(RpcTransport::Pipe(name.to_string())),
+            _ => Err(format!(
+                "unsupported rpc transport `{}`, expected `stdio`, `unix:PATH`, `tcp:PORT` or `pipe:NAME`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct AppConfig {
     debug: bool,
+    capture_from: Option<PathBuf>,
+    windows_from: Option<PathBuf>,
+    format: ImageFormat,
+    quality: u8,
+    png_compression: PngCompression,
+    save_dir: Option<PathBuf>,
+    filename_template: String,
+    post_capture: Vec<PostCaptureAction>,
+    save_handler: SaveHandler,
+    image_output: ImageOutput,
+    theme: Theme,
+    keymap: BTreeMap<String, String>,
+    mode: CaptureMode,
+    delay: Duration,
+    rpc_transport: RpcTransport,
+    rpc_framing: Framing,
+    wait_initialize: bool,
+    parent_pid: Option<u32>,
+    idle_timeout: Option<Duration>,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
-        Self { debug: false }
+        Self {
+            debug: false,
+            capture_from: None,
+            windows_from: None,
+            format: ImageFormat::default(),
+            quality: EncodeOptions::default().quality,
+            png_compression: PngCompression::default(),
+            save_dir: None,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            post_capture: vec![PostCaptureAction::Copy],
+            save_handler: SaveHandler::default(),
+            image_output: ImageOutput::default(),
+            theme: Theme::default(),
+            keymap: BTreeMap::new(),
+            mode: CaptureMode::default(),
+            delay: Duration::ZERO,
+            rpc_transport: RpcTransport::default(),
+            rpc_framing: Framing::default(),
+            wait_initialize: false,
+            parent_pid: None,
+            idle_timeout: None,
+        }
     }
 }
 
-const DEBUG_ARG: &str = "--debug";
-
-
 impl AppConfig {
+    /// 解析命令行参数并加载配置文件，参数或配置文件错误时打印错误并退出进程
     pub fn from_args() -> Self {
-        let args = std::env::args().collect::<Vec<String>>();
-        let debug = args.contains(&DEBUG_ARG.to_string());
-        Self { debug }
+        Cli::parse().options.to_config_or_exit()
     }
 
     pub fn is_debug(&self) -> bool {
         self.debug
     }
+
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// 保存对话框的默认目录，未设置时为下载目录，没有下载目录时为当前目录或临时目录
+    pub fn save_dir(&self) -> PathBuf {
+        if let Some(dir) = self.save_dir.clone().or_else(dirs::download_dir) {
+            return dir;
+        }
+        let dir = std::env::current_dir().unwrap_or_else(|_| std::env::temp_dir());
+        log::warn!("No download directory, falling back to {:?}", dir);
+        dir
+    }
+
+    pub fn encode_options(&self) -> EncodeOptions {
+        EncodeOptions {
+            format: self.format,
+            quality: self.quality,
+            compression: self.png_compression,
+        }
+    }
+
+    /// 按 filename_template 生成带扩展名的文件名，模板非法时使用默认模板
+    pub fn file_name(&self) -> String {
+        let now = Local::now();
+        let mut name = String::new();
+        if write!(name, "{}", now.format(&self.filename_template)).is_err() {
+            name = now.format(DEFAULT_FILENAME_TEMPLATE).to_string();
+        }
+        format!("{}.{}", name, self.format.extension())
+    }
+
+    pub fn post_capture(&self) -> &[PostCaptureAction] {
+        &self.post_capture
+    }
+
+    /// 宿主也可以在 initialize 中选择
+    pub fn save_handler(&self) -> SaveHandler {
+        self.save_handler
+    }
+
+    /// 宿主也可以在 initialize 中选择
+    pub fn image_output(&self) -> ImageOutput {
+        self.image_output
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    /// 动作 -> 按键，未设置的动作使用界面的默认按键
+    pub fn keymap(&self) -> &BTreeMap<String, String> {
+        &self.keymap
+    }
+
+    pub fn mode(&self) -> CaptureMode {
+        self.mode
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    pub fn rpc_transport(&self) -> &RpcTransport {
+        &self.rpc_transport
+    }
+
+    pub fn rpc_framing(&self) -> Framing {
+        self.rpc_framing
+    }
+
+    /// 是否在宿主完成 initialize 握手后才显示界面
+    pub fn wait_initialize(&self) -> bool {
+        self.wait_initialize
+    }
+
+    /// 退出时一起退出的进程，未设置时 Unix 上监视启动 quickcap 的父进程
+    pub fn parent_pid(&self) -> Option<u32> {
+        self.parent_pid
+    }
+
+    /// 没有操作和 RPC 消息超过这个时间后退出，None 为不超时
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    pub fn capture_from(&self) -> Option<&Path> {
+        self.capture_from.as_deref()
+    }
+
+    pub fn windows_from(&self) -> Option<&Path> {
+        self.windows_from.as_deref()
+    }
+
+    /// 设置了 capture_from 时使用文件中的截图和窗口列表，否则使用当前平台的后端
+    pub fn capture_backend(&self) -> Result<Box<dyn CaptureBackend>, CaptureError> {
+        match &self.capture_from {
+            Some(frame_path) => Ok(Box::new(FileBackend::new(
+                frame_path,
+                self.windows_from.as_deref(),
+            )?)),
+            None => Ok(capscreen::default_backend()),
+        }
+    }
 }
 
 pub struct AppConfigBuilder {
@@ -41,6 +338,148 @@ impl AppConfigBuilder {
         self
     }
 
+    /// 从 PNG 文件加载截图而不是截取屏幕，用于没有屏幕的测试环境
+    pub fn with_capture_from(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.capture_from = Some(path.into());
+        self
+    }
+
+    /// 从 JSON 文件加载窗口列表，只在 capture_from 设置时生效
+    pub fn with_windows_from(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.windows_from = Some(path.into());
+        self
+    }
+
+    /// 保存和复制的图片格式
+    pub fn with_format(mut self, format: ImageFormat) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// 保存对话框的默认目录
+    pub fn with_save_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.save_dir = Some(dir.into());
+        self
+    }
+
+    /// JPEG 质量，1-100
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.config.quality = quality.clamp(1, 100);
+        self
+    }
+
+    pub fn with_png_compression(mut self, compression: PngCompression) -> Self {
+        self.config.png_compression = compression;
+        self
+    }
+
+    /// 保存时的文件名模板，chrono 的 strftime 格式，不包含扩展名
+    pub fn with_filename_template(mut self, template: impl Into<String>) -> Self {
+        self.config.filename_template = template.into();
+        self
+    }
+
+    /// 确认选区后按顺序执行的动作
+    pub fn with_post_capture(mut self, actions: Vec<PostCaptureAction>) -> Self {
+        self.config.post_capture = actions;
+        self
+    }
+
+    /// 保存时使用系统对话框、宿主选择的路径或把图片发送给宿主
+    pub fn with_save_handler(mut self, handler: SaveHandler) -> Self {
+        self.config.save_handler = handler;
+        self
+    }
+
+    /// 最终的图片通过 capture_result 交给宿主，此时确认选区不再执行 post_capture
+    pub fn with_image_output(mut self, output: ImageOutput) -> Self {
+        self.config.image_output = output;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.config.theme = theme;
+        self
+    }
+
+    /// 动作 -> 按键，按键使用 KeyboardEvent.key 的值
+    pub fn with_keymap(mut self, keymap: BTreeMap<String, String>) -> Self {
+        self.config.keymap = keymap;
+        self
+    }
+
+    /// 应用配置文件中设置了的值，应在命令行参数之前调用，使命令行参数优先
+    pub fn with_config_file(mut self, file: ConfigFile) -> Self {
+        if let Some(dir) = file.save_dir {
+            self = self.with_save_dir(dir);
+        }
+        if let Some(template) = file.filename_template {
+            self = self.with_filename_template(template);
+        }
+        if let Some(format) = file.format {
+            self = self.with_format(format);
+        }
+        if let Some(quality) = file.quality {
+            self = self.with_quality(quality);
+        }
+        if let Some(compression) = file.png_compression {
+            self = self.with_png_compression(compression);
+        }
+        if let Some(actions) = file.post_capture {
+            self = self.with_post_capture(actions);
+        }
+        if let Some(theme) = file.theme {
+            self = self.with_theme(theme);
+        }
+        if let Some(keymap) = file.keymap {
+            self = self.with_keymap(keymap);
+        }
+        if let Some(mode) = file.mode {
+            self = self.with_mode(mode);
+        }
+        self
+    }
+
+    pub fn with_mode(mut self, mode: CaptureMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// 截图前等待的时间，用于截取菜单等需要先操作的界面
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.config.delay = delay;
+        self
+    }
+
+    pub fn with_rpc_transport(mut self, transport: RpcTransport) -> Self {
+        self.config.rpc_transport = transport;
+        self
+    }
+
+    /// 发送 JSON-RPC 消息的分帧方式，宿主也可以在 initialize 中切换
+    pub fn with_rpc_framing(mut self, framing: Framing) -> Self {
+        self.config.rpc_framing = framing;
+        self
+    }
+
+    /// 界面保持隐藏，直到宿主通过 JSON-RPC 完成 initialize 握手
+    pub fn with_wait_initialize(mut self, wait: bool) -> Self {
+        self.config.wait_initialize = wait;
+        self
+    }
+
+    /// 监视的宿主进程，该进程退出后 quickcap 也退出
+    pub fn with_parent_pid(mut self, pid: u32) -> Self {
+        self.config.parent_pid = Some(pid);
+        self
+    }
+
+    /// 没有操作和 RPC 消息超过这个时间后退出，零为不超时
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.idle_timeout = Some(timeout).filter(|timeout| !timeout.is_zero());
+        self
+    }
+
     pub fn build(self) -> AppConfig {
         self.config
     }
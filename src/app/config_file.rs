@@ -0,0 +1,199 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use chrono::format::{Item, StrftimeItems};
+use serde::Deserialize;
+use toml::Table;
+
+use crate::{
+    app::config::{CaptureMode, PostCaptureAction, Theme},
+    capscreen::encode::{ImageFormat, PngCompression},
+};
+
+/// 配置文件中允许出现的键
+const KNOWN_KEYS: &[&str] = &[
+    "save_dir",
+    "filename_template",
+    "format",
+    "quality",
+    "png_compression",
+    "post_capture",
+    "theme",
+    "keymap",
+    "mode",
+];
+
+/// keymap 中可以绑定的动作，与界面的默认快捷键对应(Escape、Ctrl/Cmd+S、Ctrl/Cmd+Z)
+pub const KEYMAP_ACTIONS: &[&str] = &["exit", "save", "undo"];
+
+/// `quickcap/config.toml` 的内容，所有字段都是可选的，未设置时使用 AppConfig 的默认值
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    pub save_dir: Option<PathBuf>,
+    /// chrono 的 strftime 格式，不包含扩展名
+    pub filename_template: Option<String>,
+    pub format: Option<ImageFormat>,
+    pub quality: Option<u8>,
+    pub png_compression: Option<PngCompression>,
+    pub post_capture: Option<Vec<PostCaptureAction>>,
+    pub theme: Option<Theme>,
+    /// 动作 -> 按键，按键使用 KeyboardEvent.key 的值
+    pub keymap: Option<BTreeMap<String, String>>,
+    pub mode: Option<CaptureMode>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl ConfigFile {
+    /// 默认位置 `dirs::config_dir()/quickcap/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("quickcap").join("config.toml"))
+    }
+
+    /// 读取并校验配置文件，文件不存在时返回默认值
+    /// 每个未知的键和非法的值都会单独生成一个错误
+    pub fn load(path: &Path) -> Result<Self, Vec<ConfigError>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(vec![error(path, format!("failed to read: {}", e))]),
+        };
+        Self::parse(path, &content)
+    }
+
+    pub fn parse(path: &Path, content: &str) -> Result<Self, Vec<ConfigError>> {
+        let table = content
+            .parse::<Table>()
+            .map_err(|e| vec![error(path, e.to_string())])?;
+
+        let mut errors = vec![];
+        for key in table.keys() {
+            if !KNOWN_KEYS.contains(&key.as_str()) {
+                errors.push(error(path, format!("unknown key `{}`", key)));
+            }
+        }
+        if let Some(keymap) = table.get("keymap").and_then(|keymap| keymap.as_table()) {
+            for action in keymap.keys() {
+                if !KEYMAP_ACTIONS.contains(&action.as_str()) {
+                    errors.push(error(path, format!("unknown key `keymap.{}`", action)));
+                }
+            }
+        }
+        // 未知键已经报告过，逐个字段反序列化，保证每个非法值都能报告
+        let mut config = Self::default();
+        for (key, value) in table {
+            if !KNOWN_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            let field = Table::from_iter([(key.clone(), value)]);
+            match field.try_into::<ConfigFile>() {
+                Ok(field) => config.merge(field),
+                Err(e) => errors.push(error(path, format!("invalid `{}`: {}", key, e.message()))),
+            }
+        }
+        if let Some(quality) = config.quality
+            && !(1..=100).contains(&quality)
+        {
+            errors.push(error(path, "invalid `quality`: expected 1-100".to_string()));
+        }
+        if let Some(template) = &config.filename_template
+            && StrftimeItems::new(template).any(|item| matches!(item, Item::Error))
+        {
+            errors.push(error(
+                path,
+                format!("invalid `filename_template`: bad format `{}`", template),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.save_dir = other.save_dir.or(self.save_dir.take());
+        self.filename_template = other.filename_template.or(self.filename_template.take());
+        self.format = other.format.or(self.format);
+        self.quality = other.quality.or(self.quality);
+        self.png_compression = other.png_compression.or(self.png_compression);
+        self.post_capture = other.post_capture.or(self.post_capture.take());
+        self.theme = other.theme.or(self.theme);
+        self.keymap = other.keymap.or(self.keymap.take());
+        self.mode = other.mode.or(self.mode);
+    }
+}
+
+fn error(path: &Path, message: String) -> ConfigError {
+    ConfigError {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_file() {
+        let path = Path::new("config.toml");
+        let config = ConfigFile::parse(
+            path,
+            r#"
+            save_dir = "/tmp/shots"
+            filename_template = "shot-%Y%m%d"
+            format = "jpeg"
+            quality = 80
+            post_capture = ["copy", "save"]
+            theme = "dark"
+            keymap = { exit = "q" }
+            mode = "window"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.format, Some(ImageFormat::Jpeg));
+        assert_eq!(config.quality, Some(80));
+        assert_eq!(
+            config.post_capture,
+            Some(vec![PostCaptureAction::Copy, PostCaptureAction::Save])
+        );
+        assert_eq!(config.keymap.unwrap()["exit"], "q");
+        assert_eq!(config.mode, Some(CaptureMode::Window));
+        let config = ConfigFile::parse(path, r#"format = "webp""#).unwrap();
+        assert_eq!(config.format, Some(ImageFormat::Webp));
+
+        let errors = ConfigFile::parse(
+            path,
+            r#"
+            color = "red"
+            format = "gif"
+            quality = 0
+            keymap = { paste = "v" }
+            mode = "desktop"
+            "#,
+        )
+        .unwrap_err();
+        let messages = errors
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 5, "{:?}", messages);
+        assert!(messages.contains(&"unknown key `color`"));
+        assert!(messages.contains(&"unknown key `keymap.paste`"));
+    }
+}
@@ -2,6 +2,14 @@ mod app;
 mod window;
 mod user_event;
 mod config;
+mod config_file;
+mod rpc;
+mod watchdog;
+pub(crate) mod commands;
 
-pub use app::App;
-pub use config::{AppConfig, AppConfigBuilder};
\ No newline at end of file
+pub use app::{App, AppError};
+pub use config::{
+    AppConfig, AppConfigBuilder, CaptureMode, ImageOutput, PostCaptureAction, RpcTransport,
+    SaveHandler, Theme,
+};
+pub use config_file::{ConfigError, ConfigFile};
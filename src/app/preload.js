@@ -22,6 +22,7 @@ const app = {
             headers: {
                 'x-frame-width': imageData.width,
                 'x-frame-height': imageData.height,
+                'x-capture-info': captureInfo(),
             },
             body: imageData.data,
         })
@@ -32,6 +33,7 @@ const app = {
             headers: {
                 'x-frame-width': imageData.width,
                 'x-frame-height': imageData.height,
+                'x-capture-info': captureInfo(),
             },
             body: imageData.data,
         })
@@ -49,6 +51,154 @@ const app = {
         }));
     },
     isDebug: undefined,
+    // region | window | fullscreen
+    initialMode: undefined,
+    // system | light | dark
+    theme: undefined,
+    // 动作 -> KeyboardEvent.key，来自配置文件
+    keymap: {},
+    // 虚拟桌面左上角的桌面坐标(物理像素)，macOS 为 null
+    desktopOrigin: null,
+    // getWindows 等使用的桌面坐标转换为窗口内的 CSS 像素，与宿主换算选区的方向相反
+    toCssRect: ({ x, y, width, height }) => {
+        const origin = window.app.desktopOrigin;
+        if (!origin) {
+            return { x, y, width, height };
+        }
+        const ratio = window.devicePixelRatio;
+        return {
+            x: (x - origin.x) / ratio,
+            y: (y - origin.y) / ratio,
+            width: width / ratio,
+            height: height / ratio,
+        };
+    },
+    // 由 App 通过 evaluate_script 调用，处理宿主的 JSON-RPC 请求，结果通过 ipc 返回
+    handleRpc: async (id, method, params) => {
+        let message;
+        try {
+            const result = await rpcHandlers[method](params || {});
+            message = { type: 'rpc_result', id, result };
+        } catch (e) {
+            message = e === NO_SELECTION
+                ? { type: 'rpc_result', id, skip: true }
+                : { type: 'rpc_result', id, error: { code: -32603, message: String(e && e.message || e) } };
+        }
+        window.ipc.postMessage(JSON.stringify(message));
+    },
 }
 
-window.app = app;
\ No newline at end of file
+window.app = app;
+
+// 选区(窗口内的 CSS 像素)和标注数量，退出时作为 capture_result 发送给宿主
+const captureInfo = () => {
+    const screen = window.drawScreen;
+    const hasSelection = screen && screen.selectWidth && screen.selectHeight;
+    return JSON.stringify({
+        selection: hasSelection ? {
+            x: screen.selectX,
+            y: screen.selectY,
+            width: screen.selectWidth,
+            height: screen.selectHeight,
+        } : null,
+        annotations: screen?.editCanvas?.shapeArr?.length || 0,
+    });
+};
+
+// 界面的默认快捷键，keymap 中的按键会转换为对应的默认快捷键
+const defaultShortcuts = {
+    exit: { key: 'Escape' },
+    save: { key: 's', ctrlKey: true, metaKey: true },
+    undo: { key: 'z', ctrlKey: true, metaKey: true },
+};
+
+window.addEventListener('keydown', (e) => {
+    if (!e.isTrusted) {
+        return;
+    }
+    for (const [action, key] of Object.entries(window.app.keymap || {})) {
+        const shortcut = defaultShortcuts[action];
+        if (shortcut && e.key === key) {
+            e.preventDefault();
+            e.stopImmediatePropagation();
+            window.dispatchEvent(new KeyboardEvent('keydown', shortcut));
+            return;
+        }
+    }
+}, true);
+
+// 非 system 时固定原生控件的配色，前端样式没有按主题区分，只设置 color-scheme
+window.addEventListener('DOMContentLoaded', () => {
+    if (window.app.theme && window.app.theme !== 'system') {
+        document.documentElement.style.colorScheme = window.app.theme;
+    }
+});
+
+// 当前窗口没有选区，由其他显示器的窗口处理
+const NO_SELECTION = {};
+
+const getSelection = () => {
+    const screen = window.drawScreen;
+    if (!screen || !screen.selectWidth || !screen.selectHeight) {
+        throw NO_SELECTION;
+    }
+    return {
+        x: screen.selectX,
+        y: screen.selectY,
+        width: screen.selectWidth,
+        height: screen.selectHeight,
+    };
+};
+
+// 与点击工具栏相同，进入编辑模式并显示工具栏
+const startEdit = () => {
+    const rect = getSelection();
+    window.drawScreen.setEditCanvasBg();
+    window.drawScreen.editTools.render(true, rect);
+};
+
+const exportImage = async () => {
+    startEdit();
+    return await window.drawScreen.editCanvas.generateImageData();
+};
+
+const rpcHandlers = {
+    setSelection: ({ x, y, width, height }) => {
+        const screen = window.drawScreen;
+        if (screen.mode === 'edit') {
+            throw new Error('selection cannot be changed while editing');
+        }
+        const rect = window.app.toCssRect({ x, y, width, height });
+        screen.selectX = rect.x;
+        screen.selectY = rect.y;
+        screen.selectWidth = rect.width;
+        screen.selectHeight = rect.height;
+        screen.mode = 'waitEdit';
+        screen.drawMask();
+        screen.editTools.render(true, getSelection());
+        return { x, y, width, height };
+    },
+    setTool: ({ tool }) => {
+        startEdit();
+        const shape = tool || '';
+        window.drawScreen.editCanvas.setShape(shape);
+        window.drawScreen.editTools.active = shape;
+        return { tool };
+    },
+    save: async () => {
+        const response = await window.app.saveImageToFolder(await exportImage());
+        if (!response.ok) {
+            throw new Error(await response.text());
+        }
+        // 201 为用户取消了保存对话框，save_handler 为 data 时没有路径
+        return { path: response.status === 201 ? null : (await response.text()) || null };
+    },
+    copy: async () => {
+        const imageData = await exportImage();
+        const response = await window.app.copyToClipboard(imageData);
+        if (!response.ok) {
+            throw new Error(await response.text());
+        }
+        return { width: imageData.width, height: imageData.height };
+    },
+};
@@ -0,0 +1,318 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tao::window::WindowId;
+
+use crate::{
+    StdRpcClient,
+    app::{
+        commands::{
+            Capabilities, InitializeParams, InitializeResult, PROTOCOL_VERSION, PeerInfo,
+            RpcCommand,
+        },
+        user_event::UserEvent,
+        window::AppWindow,
+    },
+    capscreen::{
+        CaptureBackend,
+        enumerate::{Rect, WindowInfo},
+    },
+    stdio::{TypedResponder, client::RpcError},
+};
+
+/// 所有窗口都没有选区，save/copy/setTool 无法执行
+const NO_SELECTION: i32 = -32000;
+/// 使用 --wait-initialize 启动时，initialize 之前的请求返回此错误，与 LSP 一致
+const SERVER_NOT_INITIALIZED: i32 = -32002;
+
+/// 以 webview 返回的 JSON 结果回复，回复前按方法的结果类型校验
+type PendingReply = Box<dyn FnOnce(Result<Value, RpcError>)>;
+
+/// webview 通过 ipc 返回的 `{type: "rpc_result", id, result | error | skip}`
+#[derive(Debug, Deserialize)]
+struct IpcResult {
+    id: Value,
+    #[serde(default)]
+    result: Value,
+    error: Option<RpcError>,
+    #[serde(default)]
+    skip: bool,
+}
+
+/// 在 GUI 线程处理宿主的 JSON-RPC 请求
+/// 能立即得到结果的请求直接回复，需要界面参与的请求转发给 webview，等待 UserEvent::RpcResult 后再回复
+pub struct RpcDispatcher {
+    backend: Arc<dyn CaptureBackend>,
+    all_windows: Arc<Vec<WindowInfo>>,
+    capabilities: Capabilities,
+    /// 是否要求宿主先完成 initialize 握手
+    wait_initialize: bool,
+    initialized: bool,
+    /// 等待 webview 返回结果的请求: id -> (回复, 还未返回结果的窗口数)
+    pending: HashMap<String, (PendingReply, usize)>,
+}
+
+impl RpcDispatcher {
+    pub fn new(
+        backend: Arc<dyn CaptureBackend>,
+        all_windows: Arc<Vec<WindowInfo>>,
+        capabilities: Capabilities,
+        wait_initialize: bool,
+    ) -> Self {
+        Self {
+            backend,
+            all_windows,
+            capabilities,
+            wait_initialize,
+            initialized: false,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// 处理已经校验过参数的请求，返回是否需要退出应用
+    pub fn handle_command(
+        &mut self,
+        command: RpcCommand,
+        windows: &HashMap<WindowId, AppWindow>,
+    ) -> bool {
+        if self.wait_initialize
+            && !self.initialized
+            && !matches!(command, RpcCommand::Initialize(..) | RpcCommand::Exit(_))
+        {
+            command.reject(RpcError::new(
+                SERVER_NOT_INITIALIZED,
+                "server not initialized",
+            ));
+            return false;
+        }
+        match command {
+            RpcCommand::Initialize(params, responder) => {
+                let framing = params.framing;
+                let save_handler = params.save_handler;
+                let image_output = params.image_output;
+                let result = self.initialize(params);
+                let initialized = result.is_ok();
+                responder.respond(result);
+                // 回复使用原来的分帧方式，之后的消息使用宿主选择的方式
+                if let Some(framing) = framing.filter(|_| initialized) {
+                    StdRpcClient::global().set_framing(framing);
+                }
+                if let Some(handler) = save_handler.filter(|_| initialized) {
+                    for window in windows.values() {
+                        window.set_save_handler(handler);
+                    }
+                }
+                if let Some(output) = image_output.filter(|_| initialized) {
+                    for window in windows.values() {
+                        window.set_image_output(output);
+                    }
+                }
+                // 握手完成后才显示界面
+                if initialized && self.wait_initialize {
+                    for window in windows.values() {
+                        window.window.set_visible(true);
+                        window.window.set_focus();
+                    }
+                }
+            }
+            RpcCommand::Exit(responder) => {
+                responder.respond(Ok(()));
+                return true;
+            }
+            RpcCommand::GetWindows(responder) => {
+                responder.respond(Ok(self.all_windows.to_vec()));
+            }
+            RpcCommand::GetMonitors(responder) => {
+                // Wayland 等后端需要连接合成器，在工作线程中获取，避免阻塞事件循环
+                let backend = Arc::clone(&self.backend);
+                std::thread::spawn(move || {
+                    responder.respond(
+                        backend
+                            .list_monitors()
+                            .map_err(|e| RpcError::internal_error(format!("{:?}", e))),
+                    );
+                });
+            }
+            RpcCommand::SetSelection(rect, responder) => match self.locate_selection(rect, windows)
+            {
+                Ok((window, local)) => self.forward(responder, "setSelection", &local, window),
+                Err(e) => responder.respond(Err(e)),
+            },
+            RpcCommand::SetTool(params, responder) => {
+                self.forward(responder, "setTool", &params, windows.values())
+            }
+            RpcCommand::Save(responder) => {
+                self.forward(responder, "save", &Value::Null, windows.values())
+            }
+            RpcCommand::Copy(responder) => {
+                self.forward(responder, "copy", &Value::Null, windows.values())
+            }
+        }
+        false
+    }
+
+    /// 收到 webview 的结果，第一个返回结果的窗口作为回复，所有窗口都没有选区时回复错误
+    pub fn handle_result(&mut self, id: Value, result: Option<Result<Value, RpcError>>) {
+        let key = id.to_string();
+        let Some((_, remaining)) = self.pending.get_mut(&key) else {
+            log::error!("Ignore rpc result without pending request: {}", key);
+            return;
+        };
+        let result = match result {
+            Some(result) => result,
+            None if *remaining > 1 => {
+                *remaining -= 1;
+                return;
+            }
+            None => Err(RpcError::new(NO_SELECTION, "no selection")),
+        };
+        if let Some((reply, _)) = self.pending.remove(&key) {
+            reply(result);
+        }
+    }
+
+    /// 退出前回复所有还在等待 webview 的请求，避免宿主一直等待
+    pub fn cancel_pending(&mut self) {
+        for (_, (reply, _)) in self.pending.drain() {
+            reply(Err(RpcError::internal_error("application is exiting")));
+        }
+    }
+
+    /// 校验宿主的协议版本并按宿主的选择过滤通知
+    fn initialize(&mut self, params: InitializeParams) -> Result<InitializeResult, RpcError> {
+        if self.initialized {
+            return Err(RpcError::invalid_request("already initialized"));
+        }
+        if params.protocol_version != PROTOCOL_VERSION {
+            return Err(RpcError {
+                data: Some(serde_json::json!({ "protocol_version": PROTOCOL_VERSION })),
+                ..RpcError::invalid_params(format!(
+                    "unsupported protocol version {}, expected {}",
+                    params.protocol_version, PROTOCOL_VERSION
+                ))
+            });
+        }
+        if let Some(notifications) = &params.notifications
+            && let Some(unknown) = notifications
+                .iter()
+                .find(|name| !self.capabilities.notifications.contains(name))
+        {
+            return Err(RpcError::invalid_params(format!(
+                "unknown notification `{}`",
+                unknown
+            )));
+        }
+        log::error!("initialize from {:?}", params.client_info);
+        StdRpcClient::global()
+            .set_notification_filter(params.notifications.map(HashSet::from_iter));
+        self.initialized = true;
+        Ok(InitializeResult {
+            protocol_version: PROTOCOL_VERSION,
+            server_info: PeerInfo {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            },
+            platform: std::env::consts::OS.to_string(),
+            backend: self.backend.name().to_string(),
+            capabilities: self.capabilities.clone(),
+        })
+    }
+
+    /// 选区使用与 getWindows 相同的桌面坐标，macOS 需要转换为所在显示器的窗口坐标
+    fn locate_selection<'a>(
+        &self,
+        rect: Rect,
+        windows: &'a HashMap<WindowId, AppWindow>,
+    ) -> Result<(Option<&'a AppWindow>, Rect), RpcError> {
+        if rect.width <= 0.0 || rect.height <= 0.0 {
+            return Err(RpcError::invalid_params(
+                "width and height must be positive",
+            ));
+        }
+        if self.backend.capabilities().virtual_desktop {
+            return Ok((windows.values().next(), rect));
+        }
+        let monitor = self
+            .backend
+            .list_monitors()
+            .map_err(|e| RpcError::internal_error(format!("{:?}", e)))?
+            .into_iter()
+            .find(|monitor| {
+                let bounds = &monitor.bounds;
+                rect.x >= bounds.x
+                    && rect.x < bounds.x + bounds.width
+                    && rect.y >= bounds.y
+                    && rect.y < bounds.y + bounds.height
+            })
+            .ok_or_else(|| RpcError::invalid_params("selection is outside of all monitors"))?;
+        let window = windows
+            .values()
+            .find(|window| window.monitor_id() == monitor.id);
+        let local = Rect {
+            x: rect.x - monitor.bounds.x,
+            y: rect.y - monitor.bounds.y,
+            ..rect
+        };
+        Ok((window, local))
+    }
+
+    /// 在窗口的 webview 中调用 window.app.handleRpc，结果通过 ipc 返回
+    fn forward<'a, R>(
+        &mut self,
+        responder: TypedResponder<R>,
+        method: &str,
+        params: &impl Serialize,
+        windows: impl IntoIterator<Item = &'a AppWindow>,
+    ) where
+        R: Serialize + DeserializeOwned + 'static,
+    {
+        let params = match serde_json::to_value(params) {
+            Ok(params) => params,
+            Err(e) => return responder.respond(Err(RpcError::internal_error(e.to_string()))),
+        };
+        let script = format!(
+            "window.app.handleRpc({}, {}, {})",
+            responder.id(),
+            Value::from(method),
+            params
+        );
+        let mut count = 0;
+        for window in windows {
+            let Some(webview) = &window.webview else {
+                continue;
+            };
+            match webview.evaluate_script(&script) {
+                Ok(()) => count += 1,
+                Err(e) => log::error!("Failed to evaluate rpc script: {}", e),
+            }
+        }
+        if count == 0 {
+            return responder.respond(Err(RpcError::internal_error("no overlay window")));
+        }
+        let key = responder.id().to_string();
+        self.pending.insert(
+            key,
+            (
+                Box::new(move |result| responder.respond_value(result)),
+                count,
+            ),
+        );
+    }
+}
+
+/// 解析 webview 通过 ipc 发来的 rpc_result 消息
+pub fn parse_ipc_result(msg: serde_json::Value) -> Option<UserEvent> {
+    let msg = serde_json::from_value::<IpcResult>(msg)
+        .map_err(|e| log::error!("Invalid rpc result: {}", e))
+        .ok()?;
+    let result = match (msg.skip, msg.error) {
+        (true, _) => None,
+        (false, Some(e)) => Some(Err(e)),
+        (false, None) => Some(Ok(msg.result)),
+    };
+    Some(UserEvent::RpcResult { id: msg.id, result })
+}
@@ -1,8 +1,33 @@
-use crate::stdio::client::RpcRequest;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::{
+    app::commands::{CaptureResult, ExitReason, RpcCommand},
+    stdio::client::RpcError,
+};
 
 #[allow(unused)]
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum UserEvent {
-    Exit,
-    RpcMessage(RpcRequest),
+    Exit(ExitReason),
+    /// 保存、复制或截图失败，退出时作为 capture_result 通知宿主，之后的结果覆盖之前的
+    CaptureResult(CaptureResult),
+    /// 参数已经校验的请求，事件循环处理完成后通过其中的 TypedResponder 回复
+    Rpc(RpcCommand),
+    /// webview 处理完 RPC 请求后通过 ipc 返回的结果，None 表示该窗口没有选区，交给其他窗口处理
+    RpcResult {
+        id: Value,
+        result: Option<Result<Value, RpcError>>,
+    },
+    /// 其他线程中需要操作窗口或打开原生对话框时，交给事件循环线程执行
+    RunOnMainThread(MainThreadTask),
+}
+
+pub struct MainThreadTask(pub Box<dyn FnOnce() + Send>);
+
+impl fmt::Debug for MainThreadTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MainThreadTask")
+    }
 }
@@ -0,0 +1,67 @@
+use std::thread;
+
+/// Unix 上检查父进程的间隔
+#[cfg(unix)]
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// 在后台线程中等待父进程退出，退出后调用 on_exit
+/// 指定 pid 时等待该进程，否则在 Unix 上等待启动 quickcap 的父进程，Windows 上不监视
+pub fn watch_parent(pid: Option<u32>, on_exit: impl FnOnce() + Send + 'static) {
+    thread::spawn(move || {
+        if wait_for_exit(pid) {
+            on_exit();
+        }
+    });
+}
+
+/// 阻塞到进程退出，无法监视时返回 false
+#[cfg(unix)]
+fn wait_for_exit(pid: Option<u32>) -> bool {
+    use std::os::unix::process::parent_id;
+
+    let parent = parent_id();
+    // 启动时已经被 init 收养(如从桌面启动)，没有需要监视的父进程
+    if pid.is_none() && parent == 1 {
+        return false;
+    }
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let alive = match pid {
+            // 信号 0 只检查进程是否存在，EPERM 表示进程存在但属于其他用户
+            Some(pid) => {
+                let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+                result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+            }
+            // 父进程退出后会被 init 或 subreaper 收养，父进程 id 随之变化
+            None => parent_id() == parent,
+        };
+        if !alive {
+            return true;
+        }
+    }
+}
+
+#[cfg(windows)]
+fn wait_for_exit(pid: Option<u32>) -> bool {
+    use windows::Win32::{
+        Foundation::CloseHandle,
+        System::Threading::{INFINITE, OpenProcess, PROCESS_SYNCHRONIZE, WaitForSingleObject},
+    };
+
+    // Windows 没有获取父进程的标准接口，只监视 --parent-pid 指定的进程
+    let Some(pid) = pid else {
+        return false;
+    };
+    unsafe {
+        let process = match OpenProcess(PROCESS_SYNCHRONIZE, false, pid) {
+            Ok(process) => process,
+            Err(e) => {
+                log::error!("Failed to open parent process {}: {}", pid, e);
+                return false;
+            }
+        };
+        WaitForSingleObject(process, INFINITE);
+        let _ = CloseHandle(process);
+    }
+    true
+}
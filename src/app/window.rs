@@ -1,21 +1,31 @@
-use crate::app::user_event::UserEvent;
-use crate::capscreen::capscreen;
-use crate::capscreen::enumerate::WindowInfo;
-#[cfg(target_os = "macos")]
-use crate::capscreen::enumerate::filter_windows_by_display;
-use crate::{app::config::AppConfig, stdio};
+use crate::app::{
+    commands::{
+        CaptureData, CaptureOutcome, CaptureResult, ChooseSavePathParams, ChooseSavePathResult,
+        EncodedImage, ExitReason, Selection,
+    },
+    rpc,
+    user_event::{MainThreadTask, UserEvent},
+};
+use crate::capscreen::{CaptureBackend, CaptureError, backend::virtual_bounds};
+use crate::capscreen::encode::encode;
+use crate::capscreen::enumerate::{Rect, WindowInfo, filter_windows_by_monitor};
+use crate::{
+    app::config::{AppConfig, ImageOutput, PostCaptureAction, SaveHandler},
+    stdio::{self, RpcCallError, client::RpcError},
+};
 use arboard::ImageData;
-use png::{BitDepth, ColorType, Encoder, Filter};
+use serde::Deserialize;
 use std::{
     borrow::Cow,
     fs::File,
     io::BufWriter,
+    path::Path,
     sync::{Arc, Condvar, Mutex},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use tao::{
-    event_loop::EventLoop,
+    event_loop::{EventLoop, EventLoopProxy},
     monitor::MonitorHandle,
     window::{Window, WindowBuilder},
 };
@@ -28,13 +38,10 @@ use tao::platform::windows::{MonitorHandleExtWindows, WindowBuilderExtWindows};
 #[cfg(target_os = "macos")]
 use tao::platform::macos::MonitorHandleExtMacOS;
 
-use chrono::Local;
-use dirs;
 use rfd::FileDialog;
-use std::path::PathBuf;
 use wry::{
-    WebView, WebViewBuilder,
-    http::{Response, header},
+    RequestAsyncResponder, WebView, WebViewBuilder,
+    http::{HeaderMap, Request, Response, header},
 };
 
 // static FILEDATA: &[u8] = include_bytes!("demo.html");
@@ -45,6 +52,8 @@ pub struct AppWindow {
     pub window: Arc<Window>,
     pub webview: Option<WebView>,
     pub monitor: MonitorHandle,
+    save_handler: Arc<Mutex<SaveHandler>>,
+    image_output: Arc<Mutex<ImageOutput>>,
 }
 
 struct CaptureState {
@@ -54,12 +63,535 @@ struct CaptureState {
     done: bool,
 }
 
+/// 按显示器截图时，将 tao 的显示器转换为后端的显示器ID(macOS 为 CGDirectDisplayID)
+#[allow(unused_variables)]
+fn monitor_id(monitor: &MonitorHandle) -> u32 {
+    #[cfg(target_os = "macos")]
+    {
+        monitor.native_id()
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        0
+    }
+}
+
+/// 按配置的格式编码并写入文件，成功后通知宿主
+fn save_image(
+    path: &Path,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    config: &AppConfig,
+) -> Result<(), CaptureError> {
+    let start = Instant::now();
+    let file = File::create(path).map_err(|e| {
+        log::error!("create {:?} failed: {}", path, e);
+        CaptureError::FailedToEncodeImage
+    })?;
+    encode(
+        BufWriter::new(file),
+        data,
+        width,
+        height,
+        &config.encode_options(),
+    )?;
+    crate::StdRpcClient::global().send_notification(
+        "save_image_to_folder",
+        Some(serde_json::json!({
+            "path": path.to_string_lossy(),
+        })),
+    );
+    log::error!("save image time: {:?}", start.elapsed());
+    Ok(())
+}
+
+fn copy_image(data: &[u8], width: usize, height: usize) -> Result<(), arboard::Error> {
+    let start = Instant::now();
+    let image = ImageData {
+        width,
+        height,
+        bytes: Cow::Borrowed(data),
+    };
+    arboard::Clipboard::new()?.set_image(image)?;
+    log::error!("set image time: {:?}", start.elapsed());
+    crate::StdRpcClient::global().send_notification(
+        "copy_to_clipboard",
+        Some(serde_json::json!({
+            "width": width,
+            "height": height,
+        })),
+    );
+    Ok(())
+}
+
+/// 前端在 /save 和 /copy 请求的 x-capture-info 头部中带上的信息
+#[derive(Debug, Default, Deserialize)]
+struct CaptureInfo {
+    /// 窗口内的 CSS 像素
+    selection: Option<Rect>,
+    annotations: usize,
+}
+
+impl CaptureInfo {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get("x-capture-info")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                serde_json::from_str(value)
+                    .map_err(|e| log::error!("Invalid x-capture-info: {}", e))
+                    .ok()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// 生成 capture_result 需要的窗口和后端信息，结果通过事件交给 App 在退出时发送
+#[derive(Clone)]
+struct ResultSender {
+    window: Arc<Window>,
+    monitor_id: u32,
+    backend: Arc<dyn CaptureBackend>,
+    all_windows: Arc<Vec<WindowInfo>>,
+    proxy: EventLoopProxy<UserEvent>,
+    /// 宿主可以在 initialize 中修改
+    image_output: Arc<Mutex<ImageOutput>>,
+}
+
+impl ResultSender {
+    fn image_output(&self) -> ImageOutput {
+        *self.image_output.lock().unwrap()
+    }
+
+    fn send(
+        &self,
+        outcome: CaptureOutcome,
+        info: &CaptureInfo,
+        path: Option<&Path>,
+        error: Option<String>,
+    ) {
+        self.send_result(outcome, info, path, error, None);
+    }
+
+    /// 按 image_output 在结果中带上图片，编码失败时结果为 error 并返回错误
+    fn send_with_image(
+        &self,
+        outcome: CaptureOutcome,
+        image: &ImageRequest,
+        path: Option<&Path>,
+        config: &AppConfig,
+    ) -> Result<(), String> {
+        match output_image(image, config, self.image_output()) {
+            Ok(encoded) => {
+                self.send_result(outcome, &image.info, path, None, encoded);
+                Ok(())
+            }
+            Err(error) => {
+                log::error!("{}", error);
+                self.send_result(
+                    CaptureOutcome::Error,
+                    &image.info,
+                    path,
+                    Some(error.clone()),
+                    None,
+                );
+                Err(error)
+            }
+        }
+    }
+
+    fn send_result(
+        &self,
+        outcome: CaptureOutcome,
+        info: &CaptureInfo,
+        path: Option<&Path>,
+        error: Option<String>,
+        image: Option<EncodedImage>,
+    ) {
+        let selection = info.selection.as_ref().map(|rect| self.selection(rect));
+        let (monitor, window) = match &selection {
+            Some(selection) => self.locate(selection),
+            None => (None, None),
+        };
+        let result = CaptureResult {
+            outcome,
+            selection,
+            monitor,
+            window,
+            path: path.map(|path| path.to_string_lossy().into_owned()),
+            annotations: info.annotations,
+            error,
+            image,
+        };
+        if let Err(e) = self.proxy.send_event(UserEvent::CaptureResult(result)) {
+            log::error!("send event failed: {:?}", e);
+        }
+    }
+
+    /// 窗口内的 CSS 像素转换为桌面的物理和逻辑像素
+    fn selection(&self, rect: &Rect) -> Selection {
+        let scale_factor = self.window.scale_factor();
+        // 虚拟桌面后端只有一个覆盖所有显示器的窗口，窗口原点对应后端坐标中的虚拟桌面原点
+        // Windows 上后端的坐标以虚拟桌面左上角为原点，不能直接使用窗口的屏幕坐标
+        let (origin_x, origin_y) = if self.backend.capabilities().virtual_desktop {
+            let monitors = self.backend.list_monitors().unwrap_or_default();
+            let bounds = virtual_bounds(&monitors);
+            (bounds.x, bounds.y)
+        } else {
+            let origin = self.window.inner_position().unwrap_or_default();
+            (origin.x as f64, origin.y as f64)
+        };
+        let physical = Rect {
+            x: origin_x + rect.x * scale_factor,
+            y: origin_y + rect.y * scale_factor,
+            width: rect.width * scale_factor,
+            height: rect.height * scale_factor,
+        };
+        let logical = Rect {
+            x: physical.x / scale_factor,
+            y: physical.y / scale_factor,
+            width: rect.width,
+            height: rect.height,
+        };
+        Selection {
+            physical,
+            logical,
+            scale_factor,
+        }
+    }
+
+    /// 选区中心所在的显示器和最上层的窗口，使用与 getWindows 相同的桌面坐标
+    fn locate(&self, selection: &Selection) -> (Option<u32>, Option<WindowInfo>) {
+        let virtual_desktop = self.backend.capabilities().virtual_desktop;
+        let rect = if virtual_desktop {
+            &selection.physical
+        } else {
+            &selection.logical
+        };
+        let (x, y) = (rect.x + rect.width / 2.0, rect.y + rect.height / 2.0);
+        let contains = |bounds: &Rect| {
+            x >= bounds.x
+                && x < bounds.x + bounds.width
+                && y >= bounds.y
+                && y < bounds.y + bounds.height
+        };
+        // macOS 每个显示器一个窗口，选区一定在窗口所在的显示器上
+        let monitor = if virtual_desktop {
+            self.backend
+                .list_monitors()
+                .ok()
+                .and_then(|monitors| {
+                    monitors
+                        .into_iter()
+                        .find(|monitor| contains(&monitor.bounds))
+                })
+                .map(|monitor| monitor.id)
+        } else {
+            Some(self.monitor_id)
+        };
+        let window = self
+            .all_windows
+            .iter()
+            .find(|window| contains(&window.bounds))
+            .cloned();
+        (monitor, window)
+    }
+}
+
+/// /save 和 /copy 请求中的图片和截图信息
+struct ImageRequest {
+    width: u32,
+    height: u32,
+    info: CaptureInfo,
+    /// RGBA8 像素
+    data: Vec<u8>,
+}
+
+impl ImageRequest {
+    /// 缺少 x-frame-width 或 x-frame-height 时返回 None
+    fn parse(req: Request<Vec<u8>>) -> Option<Self> {
+        let headers = req.headers();
+        let size = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u32>().ok())
+        };
+        let (width, height) = (size("x-frame-width")?, size("x-frame-height")?);
+        let info = CaptureInfo::from_headers(headers);
+        Some(Self {
+            width,
+            height,
+            info,
+            data: req.into_body(),
+        })
+    }
+}
+
+fn text_response(status: u16, body: impl Into<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    Response::builder()
+        .status(status)
+        .body(body.into())
+        .unwrap()
+        .map(Into::into)
+}
+
+/// 按 image_output 编码最终的图片，与保存文件使用相同的编码器和参数
+fn output_image(
+    image: &ImageRequest,
+    config: &AppConfig,
+    output: ImageOutput,
+) -> Result<Option<EncodedImage>, String> {
+    match output {
+        ImageOutput::None => Ok(None),
+        ImageOutput::Inline => encode_inline(image, config).map(Some),
+        ImageOutput::File => encode_to_temp_file(image, config).map(Some),
+    }
+}
+
+/// 编码到内存，base64 放在 data 中
+fn encode_inline(image: &ImageRequest, config: &AppConfig) -> Result<EncodedImage, String> {
+    let options = config.encode_options();
+    let mut bytes = vec![];
+    encode(&mut bytes, &image.data, image.width, image.height, &options)
+        .map_err(|e| format!("encode image failed: {:?}", e))?;
+    Ok(EncodedImage {
+        data: Some(stdio::base64::encode(&bytes)),
+        ..EncodedImage::new(options.format, image.width, image.height)
+    })
+}
+
+/// 写入系统临时目录，文件由宿主读取后删除
+fn encode_to_temp_file(image: &ImageRequest, config: &AppConfig) -> Result<EncodedImage, String> {
+    let options = config.encode_options();
+    // filename_template 可能包含 / 或 ..，只使用最后一段，保证文件在临时目录中
+    let file_name = config.file_name();
+    let file_name = Path::new(&file_name)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| format!("capture.{}", options.format.extension()));
+    let path = std::env::temp_dir().join(format!("quickcap-{}-{}", std::process::id(), file_name));
+    let file = File::create(&path).map_err(|e| format!("create {:?} failed: {}", path, e))?;
+    encode(
+        BufWriter::new(file),
+        &image.data,
+        image.width,
+        image.height,
+        &options,
+    )
+    .map_err(|e| format!("encode image failed: {:?}", e))?;
+    Ok(EncodedImage {
+        path: Some(path.to_string_lossy().into_owned()),
+        ..EncodedImage::new(options.format, image.width, image.height)
+    })
+}
+
+/// 保存到用户或宿主选择的路径并记录结果，成功时返回保存的路径，宿主通过 RPC 保存时需要
+fn save_to_path(
+    path: &Path,
+    image: &ImageRequest,
+    config: &AppConfig,
+    result_sender: &ResultSender,
+) -> Response<Cow<'static, [u8]>> {
+    match save_image(path, &image.data, image.width, image.height, config) {
+        Ok(()) => {
+            match result_sender.send_with_image(CaptureOutcome::Saved, image, Some(path), config) {
+                Ok(()) => text_response(200, path.to_string_lossy().as_bytes()),
+                Err(error) => text_response(500, error),
+            }
+        }
+        Err(e) => {
+            let error = format!("{:?}", e);
+            result_sender.send(
+                CaptureOutcome::Error,
+                &image.info,
+                None,
+                Some(error.clone()),
+            );
+            text_response(500, error)
+        }
+    }
+}
+
+/// 在系统保存对话框中选择路径后保存，对话框需要在事件循环线程中打开
+fn save_with_dialog(
+    image: &ImageRequest,
+    config: &AppConfig,
+    window: &Window,
+    result_sender: &ResultSender,
+) -> Response<Cow<'static, [u8]>> {
+    let format = config.format();
+    let file_path = FileDialog::new()
+        .add_filter(format.name(), &[format.extension()])
+        .set_directory(config.save_dir())
+        .set_can_create_directories(true)
+        .set_file_name(config.file_name())
+        .set_parent(window)
+        .save_file();
+    let Some(file_path) = file_path else {
+        return text_response(201, "cancel");
+    };
+    save_to_path(&file_path, image, config, result_sender)
+}
+
+/// 宿主在自己的对话框中选择路径，等待时间比普通请求长得多
+const CHOOSE_SAVE_PATH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 在事件循环线程中重新显示蒙层，再执行 f 并响应 webview
+fn respond_with_overlay(
+    result_sender: ResultSender,
+    responder: RequestAsyncResponder,
+    f: impl FnOnce(&Window, &ResultSender) -> Response<Cow<'static, [u8]>> + Send + 'static,
+) {
+    let proxy = result_sender.proxy.clone();
+    let task = MainThreadTask(Box::new(move || {
+        let window = &result_sender.window;
+        window.set_visible(true);
+        window.set_focus();
+        responder.respond(f(window, &result_sender));
+    }));
+    if let Err(e) = proxy.send_event(UserEvent::RunOnMainThread(task)) {
+        log::error!("send event failed: {:?}", e);
+    }
+}
+
+/// save_handler 为 host 或 data 时处理 /save，在其他线程等待宿主或编码，完成后再响应 webview
+struct HostSaver {
+    config: AppConfig,
+    result_sender: ResultSender,
+}
+
+impl HostSaver {
+    /// 调用宿主的 chooseSavePath 获取路径后保存，宿主返回 null 时与取消保存对话框相同
+    /// 宿主没有实现该方法或已经断开时改用系统保存对话框
+    fn choose_path(&self, req: Request<Vec<u8>>, responder: RequestAsyncResponder) {
+        let Some(image) = ImageRequest::parse(req) else {
+            return responder.respond(text_response(400, "missing frame size"));
+        };
+        let params = ChooseSavePathParams {
+            file_name: self.config.file_name(),
+            directory: self.config.save_dir().to_string_lossy().into_owned(),
+            format: self.config.format(),
+        };
+        // 蒙层在最上层，会挡住宿主的对话框
+        self.result_sender.window.set_visible(false);
+        let config = self.config.clone();
+        let result_sender = self.result_sender.clone();
+        crate::StdRpcClient::global().call_async(
+            "chooseSavePath",
+            Some(serde_json::to_value(params).unwrap()),
+            CHOOSE_SAVE_PATH_TIMEOUT,
+            move |result| {
+                let result = match result {
+                    Err(
+                        e @ (RpcCallError::Disconnected
+                        | RpcCallError::Remote(RpcError {
+                            code: RpcError::METHOD_NOT_FOUND,
+                            ..
+                        })),
+                    ) => {
+                        log::error!("chooseSavePath unavailable, using the save dialog: {}", e);
+                        return respond_with_overlay(
+                            result_sender,
+                            responder,
+                            move |window, result_sender| {
+                                save_with_dialog(&image, &config, window, result_sender)
+                            },
+                        );
+                    }
+                    result => result
+                        .map_err(|e| format!("chooseSavePath failed: {}", e))
+                        .and_then(|value| {
+                            serde_json::from_value::<ChooseSavePathResult>(value)
+                                .map_err(|e| format!("invalid chooseSavePath result: {}", e))
+                        }),
+                };
+                let response = match result {
+                    Ok(ChooseSavePathResult { path: Some(path) }) => {
+                        save_to_path(Path::new(&path), &image, &config, &result_sender)
+                    }
+                    Ok(ChooseSavePathResult { path: None }) => text_response(201, "cancel"),
+                    Err(error) => {
+                        log::error!("{}", error);
+                        result_sender.send(
+                            CaptureOutcome::Error,
+                            &image.info,
+                            None,
+                            Some(error.clone()),
+                        );
+                        text_response(500, error)
+                    }
+                };
+                // 保存成功后蒙层随即退出，其余情况恢复蒙层，用户可以重试
+                if response.status() == 200 {
+                    responder.respond(response);
+                } else {
+                    respond_with_overlay(result_sender, responder, move |_, _| response);
+                }
+            },
+        );
+    }
+
+    /// 不写入文件，编码后通过 capture_data 通知发送给宿主
+    fn send_data(&self, req: Request<Vec<u8>>, responder: RequestAsyncResponder) {
+        let Some(image) = ImageRequest::parse(req) else {
+            return responder.respond(text_response(400, "missing frame size"));
+        };
+        let config = self.config.clone();
+        let result_sender = self.result_sender.clone();
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            let response = match encode_inline(&image, &config) {
+                Ok(encoded) => {
+                    let data = CaptureData {
+                        file_name: config.file_name(),
+                        image: encoded,
+                    };
+                    crate::StdRpcClient::global().send_notification(
+                        "capture_data",
+                        Some(serde_json::to_value(data).unwrap()),
+                    );
+                    log::error!("send capture data time: {:?}", start.elapsed());
+                    // 没有路径，save 方法的结果中 path 为 null
+                    match result_sender.send_with_image(
+                        CaptureOutcome::Saved,
+                        &image,
+                        None,
+                        &config,
+                    ) {
+                        Ok(()) => text_response(200, ""),
+                        Err(error) => text_response(500, error),
+                    }
+                }
+                Err(error) => {
+                    log::error!("{}", error);
+                    result_sender.send(
+                        CaptureOutcome::Error,
+                        &image.info,
+                        None,
+                        Some(error.clone()),
+                    );
+                    text_response(500, error)
+                }
+            };
+            responder.respond(response);
+        });
+    }
+}
+
 impl AppWindow {
+    /// 窗口所在显示器在截图后端中的ID
+    pub fn monitor_id(&self) -> u32 {
+        monitor_id(&self.monitor)
+    }
+
     pub fn new(
         monitor: MonitorHandle,
         event_loop: &EventLoop<UserEvent>,
         config: &AppConfig,
         all_windows: Arc<Vec<WindowInfo>>,
+        backend: Arc<dyn CaptureBackend>,
     ) -> Self {
         let proxy = event_loop.create_proxy();
         #[cfg(target_os = "macos")]
@@ -97,6 +629,23 @@ impl AppWindow {
             );
             (position, size)
         };
+        #[cfg(target_os = "linux")]
+        let (position, size) = {
+            // 覆盖所有显示器的外接矩形，纯 Wayland 下没有 X 根窗口可用
+            let monitors = backend.list_monitors().unwrap_or_else(|error| {
+                log::error!("list monitors failed: {:?}", error);
+                Vec::new()
+            });
+            let bounds = virtual_bounds(&monitors);
+            let position = tao::dpi::PhysicalPosition::new(bounds.x, bounds.y);
+            let size = tao::dpi::PhysicalSize::new(bounds.width, bounds.height);
+            log::error!(
+                "create attributes: position={:?}, size={:?}",
+                position,
+                size
+            );
+            (position, size)
+        };
         let mut win_builder = WindowBuilder::new()
             .with_decorations(false)
             .with_resizable(false)
@@ -104,7 +653,8 @@ impl AppWindow {
             .with_position(position)
             .with_min_inner_size(size)
             .with_minimizable(false)
-            .with_maximizable(false);
+            .with_maximizable(false)
+            .with_visible(!config.wait_initialize());
 
         #[cfg(target_os = "macos")]
         {
@@ -116,6 +666,11 @@ impl AppWindow {
             use tao::platform::windows::WindowBuilderExtWindows;
             win_builder = win_builder.with_undecorated_shadow(false);
         }
+        #[cfg(target_os = "linux")]
+        {
+            use tao::platform::unix::WindowBuilderExtUnix;
+            win_builder = win_builder.with_skip_taskbar(true);
+        }
         let capture_state: Arc<(Mutex<CaptureState>, Condvar)> = Arc::new((
             Mutex::new(CaptureState {
                 frame: None,
@@ -129,35 +684,40 @@ impl AppWindow {
         let monitor_for_capture = monitor.clone();
         // 使用Arc共享，避免clone整个窗口列表
         let all_windows_for_thread = Arc::clone(&all_windows);
+        let backend_for_result = Arc::clone(&backend);
+        let proxy_for_capture = event_loop.create_proxy();
+        // 虚拟桌面后端的桌面坐标是物理像素，前端减去虚拟桌面原点再换算为 CSS 像素
+        // macOS 的窗口坐标已经是窗口内的逻辑像素，不需要换算
+        let desktop_origin = backend.capabilities().virtual_desktop.then(|| {
+            let bounds = virtual_bounds(&backend.list_monitors().unwrap_or_default());
+            serde_json::json!({ "x": bounds.x, "y": bounds.y })
+        });
 
         std::thread::spawn(move || {
-            // macOS: 对已枚举的窗口列表进行显示器筛选
-            // Windows: 直接使用全部窗口
-            #[cfg(target_os = "macos")]
-            let windows = {
-                use tao::platform::macos::MonitorHandleExtMacOS;
+            let start_capscreen_time = Instant::now();
+            let (result, windows) = if backend.capabilities().virtual_desktop {
+                // Windows/Linux直接使用全部窗口，坐标已经是基于虚拟桌面的
+                (
+                    backend.capture_desktop(),
+                    (*all_windows_for_thread).clone(),
+                )
+            } else {
+                // macOS: 对已枚举的窗口列表进行显示器筛选
+                let monitor_id = monitor_id(&monitor_for_capture);
                 let start_filter_time = Instant::now();
-                let display_id = monitor_for_capture.native_id();
-                // 使用Arc的引用，避免clone
-                let filtered = filter_windows_by_display(&*all_windows_for_thread, display_id)
+                let filtered = backend
+                    .list_monitors()
+                    .ok()
+                    .and_then(|monitors| monitors.into_iter().find(|m| m.id == monitor_id))
+                    .map(|monitor| filter_windows_by_monitor(&all_windows_for_thread, &monitor))
                     .unwrap_or_default();
                 log::error!(
-                    "filter windows by display time: {:?}",
+                    "filter windows by monitor time: {:?}",
                     start_filter_time.elapsed()
                 );
-                log::error!("monitor: {}, windows count: {}", display_id, filtered.len());
-                filtered
-            };
-
-            #[cfg(not(target_os = "macos"))]
-            let windows = {
-                // Windows直接使用全部窗口，坐标已经是基于虚拟桌面的
-                (*all_windows_for_thread).clone()
+                log::error!("monitor: {}, windows count: {}", monitor_id, filtered.len());
+                (backend.capture_monitor(monitor_id), filtered)
             };
-
-            // 执行截屏
-            let start_capscreen_time = Instant::now();
-            let result = capscreen(&monitor_for_capture);
             log::error!("capscreen time: {:?}", start_capscreen_time.elapsed());
 
             let (lock, cvar) = &*capture_state_for_thread;
@@ -169,7 +729,12 @@ impl AppWindow {
                 }
                 Err(e) => {
                     log::error!("capscreen failed: {:?}", e);
-                    state.error = Some(format!("capscreen failed: {:?}", e));
+                    let error = format!("capscreen failed: {:?}", e);
+                    let result = CaptureResult::new(CaptureOutcome::Error, Some(error.clone()));
+                    if let Err(e) = proxy_for_capture.send_event(UserEvent::CaptureResult(result)) {
+                        log::error!("send event failed: {:?}", e);
+                    }
+                    state.error = Some(error);
                 }
             }
             state.done = true;
@@ -177,6 +742,15 @@ impl AppWindow {
         });
 
         let window = Arc::new(win_builder.build(event_loop).unwrap());
+        let image_output = Arc::new(Mutex::new(config.image_output()));
+        let result_sender = ResultSender {
+            window: Arc::clone(&window),
+            monitor_id: monitor_id(&monitor),
+            backend: backend_for_result,
+            all_windows,
+            proxy: event_loop.create_proxy(),
+            image_output: Arc::clone(&image_output),
+        };
 
         #[cfg(target_os = "windows")]
         {
@@ -184,9 +758,222 @@ impl AppWindow {
         }
 
         let window_for_dialog = Arc::clone(&window);
+        let config_for_protocol = config.clone();
         let capture_state_for_bg = Arc::clone(&capture_state);
         let capture_state_for_windows = Arc::clone(&capture_state);
-        #[cfg(target_os = "macos")]
+        let save_handler = Arc::new(Mutex::new(config.save_handler()));
+        let save_handler_for_protocol = Arc::clone(&save_handler);
+        let host_saver = HostSaver {
+            config: config.clone(),
+            result_sender: result_sender.clone(),
+        };
+        let handle_request = move |req: Request<Vec<u8>>| -> Response<Cow<'static, [u8]>> {
+            let path = req.uri().path().to_string();
+            // log::error!("path: {:?}", path);
+            match path.as_str() {
+                "/save" => {
+                    let Some(image) = ImageRequest::parse(req) else {
+                        return text_response(400, "missing frame size");
+                    };
+                    save_with_dialog(
+                        &image,
+                        &config_for_protocol,
+                        &window_for_dialog,
+                        &result_sender,
+                    )
+                }
+                "/copy" => {
+                    let Some(image) = ImageRequest::parse(req) else {
+                        return text_response(400, "missing frame size");
+                    };
+                    let (width, height) = (image.width as usize, image.height as usize);
+                    let body = &image.data;
+                    // 图片通过 capture_result 交给宿主时，不复制也不保存
+                    let delivered = result_sender.image_output() != ImageOutput::None;
+                    let post_capture = if delivered {
+                        &[]
+                    } else {
+                        config_for_protocol.post_capture()
+                    };
+                    let mut copied = false;
+                    let mut saved_path = None;
+                    let mut errors = vec![];
+                    for action in post_capture {
+                        match action {
+                            PostCaptureAction::Copy => match copy_image(body, width, height) {
+                                Ok(()) => copied = true,
+                                Err(e) => {
+                                    log::error!("copy image failed: {}", e);
+                                    errors.push(format!("copy failed: {}", e));
+                                }
+                            },
+                            PostCaptureAction::Save => {
+                                let dir = config_for_protocol.save_dir();
+                                if let Err(e) = std::fs::create_dir_all(&dir) {
+                                    log::error!("create save dir {:?} failed: {}", dir, e);
+                                    errors.push(format!("create save dir failed: {}", e));
+                                    continue;
+                                }
+                                let path = dir.join(config_for_protocol.file_name());
+                                match save_image(
+                                    &path,
+                                    body,
+                                    width as u32,
+                                    height as u32,
+                                    &config_for_protocol,
+                                ) {
+                                    Ok(()) => saved_path = Some(path),
+                                    Err(e) => {
+                                        log::error!("auto save failed: {:?}", e);
+                                        errors.push(format!("save failed: {:?}", e));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    // 任何一个动作失败都作为错误，post_capture 为空时没有保存或复制
+                    let outcome = if !errors.is_empty() {
+                        CaptureOutcome::Error
+                    } else if copied {
+                        CaptureOutcome::Copied
+                    } else if saved_path.is_some() {
+                        CaptureOutcome::Saved
+                    } else if delivered {
+                        CaptureOutcome::Delivered
+                    } else {
+                        CaptureOutcome::Cancelled
+                    };
+                    let error = match (!errors.is_empty()).then(|| errors.join("; ")) {
+                        None if outcome != CaptureOutcome::Cancelled => result_sender
+                            .send_with_image(
+                                outcome,
+                                &image,
+                                saved_path.as_deref(),
+                                &config_for_protocol,
+                            )
+                            .err(),
+                        error => {
+                            result_sender.send(
+                                outcome,
+                                &image.info,
+                                saved_path.as_deref(),
+                                error.clone(),
+                            );
+                            error
+                        }
+                    };
+                    match error {
+                        Some(error) => Response::builder()
+                            .status(500)
+                            .body(error.into_bytes())
+                            .unwrap()
+                            .map(Into::into),
+                        None => Response::builder()
+                            .status(200)
+                            .body(b"success".to_vec())
+                            .unwrap()
+                            .map(Into::into),
+                    }
+                }
+                "/bg" => {
+                    let (lock, cvar) = &*capture_state_for_bg;
+                    let mut state = lock.lock().unwrap();
+
+                    while !state.done {
+                        state = cvar.wait(state).unwrap();
+                    }
+
+                    if let Some(error) = &state.error {
+                        log::error!("Capture error: {}", error);
+                        return Response::builder()
+                            .status(500)
+                            .header(header::CONTENT_TYPE, "text/plain")
+                            .header("Access-Control-Allow-Origin", "*")
+                            .body(error.clone().into_bytes())
+                            .unwrap()
+                            .map(Into::into);
+                    }
+
+                    if let Some(frame) = &state.frame {
+                        let data = frame.data.clone();
+                        Response::builder()
+                            .header(header::CONTENT_TYPE, "application/octet-stream")
+                            .header("Access-Control-Allow-Origin", "*")
+                            .header("Access-Control-Allow-Methods", "GET, OPTIONS")
+                            .header("Access-Control-Allow-Headers", "*")
+                            .header(
+                                "Access-Control-Expose-Headers",
+                                "x-frame-width, x-frame-height",
+                            )
+                            .header("x-frame-width", frame.width.to_string())
+                            .header("x-frame-height", frame.height.to_string())
+                            .status(200)
+                            .body(data)
+                            .unwrap()
+                            .map(Into::into)
+                    } else {
+                        Response::builder()
+                            .status(500)
+                            .header(header::CONTENT_TYPE, "text/plain")
+                            .header("Access-Control-Allow-Origin", "*")
+                            .body(b"No frame data available".to_vec())
+                            .unwrap()
+                            .map(Into::into)
+                    }
+                }
+                "/windows" => {
+                    let (lock, cvar) = &*capture_state_for_windows;
+                    let mut state = lock.lock().unwrap();
+
+                    // 如果截图尚未完成，则等待
+                    while !state.done {
+                        state = cvar.wait(state).unwrap();
+                    }
+
+                    // 检查是否有错误
+                    if let Some(error) = &state.error {
+                        log::error!("Capture error, cannot get windows: {}", error);
+                        return Response::builder()
+                            .status(500)
+                            .header(header::CONTENT_TYPE, "application/json")
+                            .header("Access-Control-Allow-Origin", "*")
+                            .header("Access-Control-Allow-Methods", "GET, OPTIONS")
+                            .header("Access-Control-Allow-Headers", "*")
+                            .body(format!(r#"{{"error": "{}"}}"#, error).into_bytes())
+                            .unwrap()
+                            .map(Into::into);
+                    }
+
+                    let windows = state.windows.clone().unwrap_or_default();
+                    log::error!("return cached windows: {:?}", windows);
+                    let json = serde_json::to_string(&windows).unwrap_or_else(|_| "[]".to_string());
+                    Response::builder()
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .header("Access-Control-Allow-Origin", "*")
+                        .header("Access-Control-Allow-Methods", "GET, OPTIONS")
+                        .header("Access-Control-Allow-Headers", "*")
+                        .status(200)
+                        .body(json.into_bytes())
+                        .unwrap()
+                        .map(Into::into)
+                }
+                "/" => Response::builder()
+                    .header(header::CONTENT_TYPE, "text/html")
+                    .header("Access-Control-Allow-Origin", "*")
+                    .header("Access-Control-Allow-Methods", "GET, OPTIONS")
+                    .header("Access-Control-Allow-Headers", "*")
+                    .status(200)
+                    .body(FILEDATA.to_vec())
+                    .unwrap()
+                    .map(Into::into),
+                _ => Response::builder()
+                    .status(404)
+                    .body(vec![])
+                    .unwrap()
+                    .map(Into::into),
+            }
+        };
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
         let webview = WebViewBuilder::new();
         #[cfg(target_os = "windows")]
         let webview = {
@@ -208,236 +995,65 @@ impl AppWindow {
             .with_transparent(true)
             .with_initialization_script(include_str!("preload.js"))
             .with_initialization_script(format!("window.app.isDebug = {}", config.is_debug()))
+            .with_initialization_script(format!(
+                "window.app.initialMode = '{}'",
+                config.mode().as_str()
+            ))
+            .with_initialization_script(format!(
+                "window.app.theme = '{}'",
+                config.theme().as_str()
+            ))
+            .with_initialization_script(format!(
+                "window.app.keymap = {}",
+                serde_json::to_string(config.keymap()).unwrap()
+            ))
+            .with_initialization_script(format!(
+                "window.app.desktopOrigin = {}",
+                serde_json::to_string(&desktop_origin).unwrap()
+            ))
             .with_ipc_handler(move |req| {
                 let body = req.body();
                 log::error!("ipc body: {:?}", body);
                 match body.as_str() {
                     "exit" => {
-                        proxy.send_event(UserEvent::Exit).unwrap_or_else(|e| {
-                            log::error!("send event failed: {:?}", e);
-                        });
+                        proxy
+                            .send_event(UserEvent::Exit(ExitReason::Closed))
+                            .unwrap_or_else(|e| {
+                                log::error!("send event failed: {:?}", e);
+                            });
                     }
                     _ => {
                         if let Ok(msg) = serde_json::from_str::<serde_json::Value>(body) {
-                            if msg.get("type").and_then(|t| t.as_str()) == Some("notify") {
-                                if let (Some(method), Some(params)) = (
-                                    msg.get("method").and_then(|m| m.as_str()),
-                                    msg.get("params"),
-                                ) {
-                                    crate::StdRpcClient::global()
-                                        .send_notification(method, Some(params.clone()));
+                            match msg.get("type").and_then(|t| t.as_str()) {
+                                Some("notify") => {
+                                    if let (Some(method), Some(params)) = (
+                                        msg.get("method").and_then(|m| m.as_str()),
+                                        msg.get("params"),
+                                    ) {
+                                        crate::StdRpcClient::global()
+                                            .send_notification(method, Some(params.clone()));
+                                    }
+                                }
+                                Some("rpc_result") => {
+                                    if let Some(event) = rpc::parse_ipc_result(msg) {
+                                        proxy.send_event(event).unwrap_or_else(|e| {
+                                            log::error!("send event failed: {:?}", e);
+                                        });
+                                    }
                                 }
+                                _ => {}
                             }
                         }
                     }
                 }
             })
-            .with_custom_protocol("app".into(), move |_name, req| {
-                let path = req.uri().path().to_string();
-                // log::error!("path: {:?}", path);
-                match path.as_str() {
-                    "/save" => {
-                        let headers = req.headers();
-                        let width = headers
-                            .get("x-frame-width")
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .parse::<u32>()
-                            .unwrap();
-                        let height = headers
-                            .get("x-frame-height")
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .parse::<u32>()
-                            .unwrap();
-
-                        let body = req.into_body();
-
-                        let download_dir =
-                            dirs::download_dir().unwrap_or_else(|| PathBuf::from("/"));
-                        let now = Local::now();
-                        let date_str = now.format("%Y%m%d");
-                        let time_str = now.format("%H%M%S");
-                        let default_name = format!("screenshot{}{}.png", date_str, time_str);
-                        let file_path = FileDialog::new()
-                            .add_filter("PNG", &["png"])
-                            .set_directory(&download_dir)
-                            .set_can_create_directories(true)
-                            .set_file_name(&default_name)
-                            .set_parent(&*window_for_dialog)
-                            .save_file();
-                        if file_path.is_none() {
-                            return Response::builder()
-                                .status(201)
-                                .body(b"cancel".to_vec())
-                                .unwrap()
-                                .map(Into::into);
-                        }
-                        let file_path = file_path.unwrap();
-                        let file_path_str = file_path.to_string_lossy().to_string();
-                        let file = File::create(file_path).unwrap();
-                        let writer = BufWriter::new(file);
-
-                        let mut encoder = Encoder::new(writer, width, height);
-                        encoder.set_color(ColorType::Rgba);
-                        encoder.set_depth(BitDepth::Eight);
-
-                        encoder.set_compression(png::Compression::NoCompression);
-                        encoder.set_filter(Filter::NoFilter);
-                        let start = Instant::now();
-                        let mut png_writer = encoder.write_header().unwrap();
-                        png_writer.write_image_data(&body).unwrap();
-                        crate::StdRpcClient::global().send_notification(
-                            "save_image_to_folder",
-                            Some(serde_json::json!({
-                                "path": file_path_str,
-                            })),
-                        );
-                        log::error!("save image time: {:?}", start.elapsed());
-                        Response::builder()
-                            .status(200)
-                            .body(b"success".to_vec())
-                            .unwrap()
-                            .map(Into::into)
-                    }
-                    "/copy" => {
-                        let headers = req.headers();
-                        let width = headers
-                            .get("x-frame-width")
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .parse::<usize>()
-                            .unwrap();
-                        let height = headers
-                            .get("x-frame-height")
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .parse::<usize>()
-                            .unwrap();
-                        let body = req.body();
-                        let start = Instant::now();
-                        let image = ImageData {
-                            width,
-                            height,
-                            bytes: Cow::Borrowed(body),
-                        };
-                        log::error!("create image time: {:?}", start.elapsed());
-                        arboard::Clipboard::new().unwrap().set_image(image).unwrap();
-                        log::error!("set image time: {:?}", start.elapsed());
-                        crate::StdRpcClient::global().send_notification(
-                            "copy_to_clipboard",
-                            Some(serde_json::json!({
-                                "width": width,
-                                "height": height,
-                            })),
-                        );
-                        Response::builder()
-                            .status(200)
-                            .body(b"success".to_vec())
-                            .unwrap()
-                            .map(Into::into)
-                    }
-                    "/bg" => {
-                        let (lock, cvar) = &*capture_state_for_bg;
-                        let mut state = lock.lock().unwrap();
-
-                        while !state.done {
-                            state = cvar.wait(state).unwrap();
-                        }
-
-                        if let Some(error) = &state.error {
-                            log::error!("Capture error: {}", error);
-                            return Response::builder()
-                                .status(500)
-                                .header(header::CONTENT_TYPE, "text/plain")
-                                .header("Access-Control-Allow-Origin", "*")
-                                .body(error.clone().into_bytes())
-                                .unwrap()
-                                .map(Into::into);
-                        }
-
-                        if let Some(frame) = &state.frame {
-                            let data = frame.data.clone();
-                            Response::builder()
-                                .header(header::CONTENT_TYPE, "application/octet-stream")
-                                .header("Access-Control-Allow-Origin", "*")
-                                .header("Access-Control-Allow-Methods", "GET, OPTIONS")
-                                .header("Access-Control-Allow-Headers", "*")
-                                .header(
-                                    "Access-Control-Expose-Headers",
-                                    "x-frame-width, x-frame-height",
-                                )
-                                .header("x-frame-width", frame.width.to_string())
-                                .header("x-frame-height", frame.height.to_string())
-                                .status(200)
-                                .body(data)
-                                .unwrap()
-                                .map(Into::into)
-                        } else {
-                            Response::builder()
-                                .status(500)
-                                .header(header::CONTENT_TYPE, "text/plain")
-                                .header("Access-Control-Allow-Origin", "*")
-                                .body(b"No frame data available".to_vec())
-                                .unwrap()
-                                .map(Into::into)
-                        }
-                    }
-                    "/windows" => {
-                        let (lock, cvar) = &*capture_state_for_windows;
-                        let mut state = lock.lock().unwrap();
-
-                        // 如果截图尚未完成，则等待
-                        while !state.done {
-                            state = cvar.wait(state).unwrap();
-                        }
-
-                        // 检查是否有错误
-                        if let Some(error) = &state.error {
-                            log::error!("Capture error, cannot get windows: {}", error);
-                            return Response::builder()
-                                .status(500)
-                                .header(header::CONTENT_TYPE, "application/json")
-                                .header("Access-Control-Allow-Origin", "*")
-                                .header("Access-Control-Allow-Methods", "GET, OPTIONS")
-                                .header("Access-Control-Allow-Headers", "*")
-                                .body(format!(r#"{{"error": "{}"}}"#, error).into_bytes())
-                                .unwrap()
-                                .map(Into::into);
-                        }
-
-                        let windows = state.windows.clone().unwrap_or_default();
-                        log::error!("return cached windows: {:?}", windows);
-                        let json =
-                            serde_json::to_string(&windows).unwrap_or_else(|_| "[]".to_string());
-                        Response::builder()
-                            .header(header::CONTENT_TYPE, "application/json")
-                            .header("Access-Control-Allow-Origin", "*")
-                            .header("Access-Control-Allow-Methods", "GET, OPTIONS")
-                            .header("Access-Control-Allow-Headers", "*")
-                            .status(200)
-                            .body(json.into_bytes())
-                            .unwrap()
-                            .map(Into::into)
-                    }
-                    "/" => Response::builder()
-                        .header(header::CONTENT_TYPE, "text/html")
-                        .header("Access-Control-Allow-Origin", "*")
-                        .header("Access-Control-Allow-Methods", "GET, OPTIONS")
-                        .header("Access-Control-Allow-Headers", "*")
-                        .status(200)
-                        .body(FILEDATA.to_vec())
-                        .unwrap()
-                        .map(Into::into),
-                    _ => Response::builder()
-                        .status(404)
-                        .body(vec![])
-                        .unwrap()
-                        .map(Into::into),
+            .with_asynchronous_custom_protocol("app".into(), move |_id, req, responder| {
+                // 宿主处理保存时需要等待宿主的回复，不能阻塞事件循环
+                let save_handler = *save_handler_for_protocol.lock().unwrap();
+                match (req.uri().path(), save_handler) {
+                    ("/save", SaveHandler::Host) => host_saver.choose_path(req, responder),
+                    ("/save", SaveHandler::Data) => host_saver.send_data(req, responder),
+                    _ => responder.respond(handle_request(req)),
                 }
             })
             .with_transparent(true)
@@ -467,6 +1083,14 @@ impl AppWindow {
                 })
                 .build_as_child(&window)
         };
+        #[cfg(target_os = "linux")]
+        let webview = {
+            // Linux 下通过 GTK 容器创建 webview，X11 和 Wayland 都适用
+            use tao::platform::unix::WindowExtUnix;
+            use wry::WebViewBuilderExtUnix;
+            let vbox = window.default_vbox().unwrap();
+            webview.build_gtk(vbox)
+        };
         let webview = match webview {
             Ok(webview) => webview,
             Err(error) => {
@@ -487,6 +1111,18 @@ impl AppWindow {
             window,
             webview: Some(webview),
             monitor,
+            save_handler,
+            image_output,
         }
     }
+
+    /// 宿主在 initialize 中选择的保存方式，之后的 /save 请求生效
+    pub fn set_save_handler(&self, handler: SaveHandler) {
+        *self.save_handler.lock().unwrap() = handler;
+    }
+
+    /// 宿主在 initialize 中选择的图片传递方式
+    pub fn set_image_output(&self, output: ImageOutput) {
+        *self.image_output.lock().unwrap() = output;
+    }
 }
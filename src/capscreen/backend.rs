@@ -0,0 +1,154 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::capscreen::{
+    CaptureError, Frame,
+    enumerate::{Rect, WindowInfo},
+};
+
+/// 显示器信息，bounds 与 WindowInfo 使用相同的桌面坐标系
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MonitorInfo {
+    pub id: u32,
+    pub name: String,
+    pub bounds: Rect,
+    pub scale_factor: f64,
+    pub is_primary: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// 一张 Frame 覆盖整个虚拟桌面(Windows/X11/Wayland)，只需要一个蒙层窗口
+    /// 为 false 时按显示器分别截图(macOS)，窗口坐标需要转换为相对显示器的坐标
+    pub virtual_desktop: bool,
+    /// 是否能枚举窗口，用于窗口感知
+    pub windows: bool,
+}
+
+/// 截图后端，各平台的实现都在此接口之后，嵌入方和测试也可以注入自己的实现
+pub trait CaptureBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>, CaptureError>;
+
+    fn capture_monitor(&self, monitor_id: u32) -> Result<Frame, CaptureError>;
+
+    /// 截取桌面坐标下的区域
+    fn capture_region(&self, region: &Rect) -> Result<Frame, CaptureError>;
+
+    /// 枚举所有窗口，使用绝对坐标
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>, CaptureError>;
+
+    /// 截取整个虚拟桌面，默认为所有显示器的外接矩形
+    fn capture_desktop(&self) -> Result<Frame, CaptureError> {
+        let monitors = self.list_monitors()?;
+        self.capture_region(&virtual_bounds(&monitors))
+    }
+}
+
+/// 所有显示器的外接矩形，即虚拟桌面
+pub fn virtual_bounds(monitors: &[MonitorInfo]) -> Rect {
+    let left = monitors.iter().map(|m| m.bounds.x).fold(f64::MAX, f64::min);
+    let top = monitors.iter().map(|m| m.bounds.y).fold(f64::MAX, f64::min);
+    let right = monitors
+        .iter()
+        .map(|m| m.bounds.x + m.bounds.width)
+        .fold(f64::MIN, f64::max);
+    let bottom = monitors
+        .iter()
+        .map(|m| m.bounds.y + m.bounds.height)
+        .fold(f64::MIN, f64::max);
+    if monitors.is_empty() {
+        return Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+        };
+    }
+    Rect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    }
+}
+
+/// 在覆盖 `area` 的 Frame 上裁剪出 `region`
+/// 桌面坐标与像素的比例按 Frame 宽度和 `area` 宽度换算，兼容逻辑坐标的平台
+pub fn crop_region(frame: &Frame, area: &Rect, region: &Rect) -> Result<Frame, CaptureError> {
+    let inside = region.x >= area.x
+        && region.y >= area.y
+        && region.x + region.width <= area.x + area.width
+        && region.y + region.height <= area.y + area.height;
+    if !inside || region.width <= 0.0 || region.height <= 0.0 {
+        log::error!("Region {:?} is out of {:?}", region, area);
+        return Err(CaptureError::RegionOutOfBounds);
+    }
+    let scale = frame.width as f64 / area.width;
+    Ok(frame.crop(
+        ((region.x - area.x) * scale).round() as u32,
+        ((region.y - area.y) * scale).round() as u32,
+        (region.width * scale).round() as u32,
+        (region.height * scale).round() as u32,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(id: u32, x: f64, y: f64, width: f64, height: f64) -> MonitorInfo {
+        MonitorInfo {
+            id,
+            name: format!("Monitor {}", id),
+            bounds: Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+            scale_factor: 1.0,
+            is_primary: id == 0,
+        }
+    }
+
+    #[test]
+    fn test_crop_region() {
+        let monitors = [
+            monitor(0, 0.0, 0.0, 2.0, 2.0),
+            monitor(1, -2.0, 0.0, 2.0, 2.0),
+        ];
+        let area = virtual_bounds(&monitors);
+        assert_eq!(
+            (area.x, area.y, area.width, area.height),
+            (-2.0, 0.0, 4.0, 2.0)
+        );
+
+        // 2倍缩放的 Frame，每个像素的 R 通道为其序号
+        let frame = Frame {
+            data: (0..8 * 4).flat_map(|i| [i as u8, 0, 0, 255]).collect(),
+            width: 8,
+            height: 4,
+        };
+        let region = Rect {
+            x: 0.0,
+            y: 1.0,
+            width: 1.0,
+            height: 1.0,
+        };
+        let cropped = crop_region(&frame, &area, &region).unwrap();
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        let red = cropped
+            .data
+            .chunks_exact(4)
+            .map(|p| p[0])
+            .collect::<Vec<_>>();
+        assert_eq!(red, vec![20, 21, 28, 29]);
+
+        let outside = Rect { x: 1.5, ..region };
+        assert!(crop_region(&frame, &area, &outside).is_err());
+    }
+}
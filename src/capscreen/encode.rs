@@ -0,0 +1,190 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use image::{
+    ExtendedColorType, ImageEncoder,
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+};
+use png::{BitDepth, ColorType, Compression, Encoder, Filter};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::capscreen::{CaptureError, Frame};
+
+/// 保存和输出的图片格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+    /// 无损压缩，不使用 quality
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Webp => "WebP",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// PNG 压缩等级
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    /// 截图通常很大，不压缩可以明显缩短保存时间
+    #[default]
+    None,
+    Fast,
+    Balanced,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeOptions {
+    pub format: ImageFormat,
+    /// JPEG 质量，1-100
+    pub quality: u8,
+    pub compression: PngCompression,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::default(),
+            quality: 90,
+            compression: PngCompression::default(),
+        }
+    }
+}
+
+/// 将 RGBA8 像素编码为指定格式并写入 writer
+pub fn encode<W: Write>(
+    writer: W,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    options: &EncodeOptions,
+) -> Result<(), CaptureError> {
+    match options.format {
+        ImageFormat::Png => {
+            let mut encoder = Encoder::new(writer, width, height);
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_depth(BitDepth::Eight);
+            let (compression, filter) = match options.compression {
+                PngCompression::None => (Compression::NoCompression, Filter::NoFilter),
+                PngCompression::Fast => (Compression::Fast, Filter::Adaptive),
+                PngCompression::Balanced => (Compression::Balanced, Filter::Adaptive),
+                PngCompression::High => (Compression::High, Filter::Adaptive),
+            };
+            encoder.set_compression(compression);
+            encoder.set_filter(filter);
+            encoder
+                .write_header()
+                .and_then(|mut writer| {
+                    writer.write_image_data(data)?;
+                    writer.finish()
+                })
+                .map_err(|e| {
+                    log::error!("Failed to encode png: {}", e);
+                    CaptureError::FailedToEncodeImage
+                })
+        }
+        ImageFormat::Jpeg => {
+            // JPEG 不支持透明通道，丢弃 alpha
+            let rgb = data
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect::<Vec<_>>();
+            JpegEncoder::new_with_quality(writer, options.quality.clamp(1, 100))
+                .write_image(&rgb, width, height, ExtendedColorType::Rgb8)
+                .map_err(|e| {
+                    log::error!("Failed to encode jpeg: {}", e);
+                    CaptureError::FailedToEncodeImage
+                })
+        }
+        ImageFormat::Webp => WebPEncoder::new_lossless(writer)
+            .write_image(data, width, height, ExtendedColorType::Rgba8)
+            .map_err(|e| {
+                log::error!("Failed to encode webp: {}", e);
+                CaptureError::FailedToEncodeImage
+            }),
+    }
+}
+
+pub fn encode_frame<W: Write>(
+    writer: W,
+    frame: &Frame,
+    options: &EncodeOptions,
+) -> Result<(), CaptureError> {
+    encode(writer, &frame.data, frame.width, frame.height, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let frame = Frame {
+            data: vec![255, 0, 0, 255, 0, 255, 0, 255],
+            width: 2,
+            height: 1,
+        };
+        let mut png = vec![];
+        let options = EncodeOptions {
+            compression: PngCompression::High,
+            ..Default::default()
+        };
+        encode_frame(&mut png, &frame, &options).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let mut jpeg = vec![];
+        let options = EncodeOptions {
+            format: ImageFormat::Jpeg,
+            quality: 50,
+            ..Default::default()
+        };
+        encode_frame(&mut jpeg, &frame, &options).unwrap();
+        assert!(jpeg.starts_with(&[0xff, 0xd8]));
+    }
+
+    #[test]
+    fn test_encode_webp() {
+        let frame = Frame {
+            data: vec![255, 0, 0, 255, 0, 255, 0, 128],
+            width: 2,
+            height: 1,
+        };
+        let mut webp = vec![];
+        let options = EncodeOptions {
+            format: ImageFormat::Webp,
+            ..Default::default()
+        };
+        encode_frame(&mut webp, &frame, &options).unwrap();
+        assert!(webp.starts_with(b"RIFF"));
+        // 无损编码，解码后与原始像素完全一致
+        let decoded = image::load_from_memory_with_format(&webp, image::ImageFormat::WebP).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (2, 1));
+        assert_eq!(decoded.to_rgba8().into_raw(), frame.data);
+    }
+}
@@ -1,47 +1,51 @@
 mod structs;
 
 #[cfg(target_os = "macos")]
-mod macos;
+pub mod macos;
 #[cfg(target_os = "windows")]
-mod windows;
+pub mod windows;
+#[cfg(target_os = "linux")]
+pub mod x11;
 
-pub use structs::WindowInfo;
-use tao::monitor::MonitorHandle;
+pub use structs::{Rect, WindowInfo};
 
-/// 枚举所有窗口（不筛选显示器）
-/// macOS和Windows都返回所有窗口，使用绝对坐标
-pub fn enumerate_all_windows() -> Option<Vec<WindowInfo>> {
-    #[cfg(target_os = "macos")]
-    {
-        macos::enumerate_all_windows()
-    }
-    #[cfg(not(target_os = "macos"))]
-    {
-        windows::enumerate_windows()
-    }
-}
+use crate::capscreen::backend::MonitorInfo;
 
-#[cfg(target_os = "macos")]
-/// macOS专用的按显示器筛选窗口
+/// 按显示器筛选窗口，用于按显示器截图的后端
 /// 输入：使用绝对坐标的窗口列表
 /// 输出：使用相对于显示器坐标的窗口列表
-pub fn filter_windows_by_display(
+pub fn filter_windows_by_monitor(
     all_windows: &[WindowInfo],
-    display_id: u32,
-) -> Option<Vec<WindowInfo>> {
-    macos::filter_windows_by_display(all_windows, display_id)
-}
+    monitor: &MonitorInfo,
+) -> Vec<WindowInfo> {
+    let display = &monitor.bounds;
+    let display_right = display.x + display.width;
+    let display_bottom = display.y + display.height;
 
-#[allow(unused)]
-pub fn enumerate_windows(handle: &MonitorHandle) -> Vec<WindowInfo> {
-    #[cfg(target_os = "macos")]
-    {
-        use tao::platform::macos::MonitorHandleExtMacOS;
+    let mut window_infos = vec![];
+    for window in all_windows {
+        let window_right = window.bounds.x + window.bounds.width;
+        let window_bottom = window.bounds.y + window.bounds.height;
 
-        macos::enumerate_windows(handle.native_id()).unwrap_or_default()
-    }
-    #[cfg(not(target_os = "macos"))]
-    {
-        windows::enumerate_windows().unwrap_or_default()
+        // 窗口可能会溢出当前显示器，只要有交集就认为在当前显示器上
+        if window_right < display.x
+            || window.bounds.x > display_right
+            || window_bottom < display.y
+            || window.bounds.y > display_bottom
+        {
+            continue;
+        }
+
+        // 前端绘制时每个显示器都以(0,0)为原点，所以需要减去显示器的origin
+        window_infos.push(WindowInfo {
+            name: window.name.clone(),
+            bounds: Rect {
+                x: window.bounds.x - display.x,
+                y: window.bounds.y - display.y,
+                width: window.bounds.width,
+                height: window.bounds.height,
+            },
+        });
     }
-}
\ No newline at end of file
+    window_infos
+}
@@ -0,0 +1,167 @@
+use crate::capscreen::enumerate::{WindowInfo, structs::Rect};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::ConnectionExt as _,
+        xproto::{AtomEnum, ConnectionExt as _, MapState, Window},
+    },
+    rust_connection::RustConnection,
+};
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_CLIENT_LIST_STACKING,
+        _NET_WM_NAME,
+        _NET_FRAME_EXTENTS,
+        _NET_WM_STATE,
+        _NET_WM_STATE_HIDDEN,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DESKTOP,
+        UTF8_STRING,
+    }
+}
+
+/// 通过 EWMH 枚举所有窗口，X11 根窗口原点即虚拟桌面原点，坐标无需再转换
+/// 顺序与 Windows 的 EnumWindows 一致，从最上层到最底层
+pub fn enumerate_windows() -> Option<Vec<WindowInfo>> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .map_err(|e| log::error!("Failed to connect to X server: {:?}", e))
+        .ok()?;
+    let root = conn.setup().roots[screen_num].root;
+    let atoms = Atoms::new(&conn).ok()?.reply().ok()?;
+
+    // _NET_CLIENT_LIST_STACKING 是从底到顶排列的
+    let clients = get_u32_property(&conn, root, atoms._NET_CLIENT_LIST_STACKING, AtomEnum::WINDOW)
+        .unwrap_or_default();
+
+    let mut window_infos: Vec<WindowInfo> = vec![];
+    for &window in clients.iter().rev() {
+        if let Some(window_info) = window_info(&conn, root, &atoms, window) {
+            window_infos.push(window_info);
+        }
+    }
+    // 将显示器作为窗口添加到window_infos中
+    window_infos.extend(monitor_infos(&conn, root));
+    Some(window_infos)
+}
+
+fn window_info(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    window: Window,
+) -> Option<WindowInfo> {
+    let attributes = conn.get_window_attributes(window).ok()?.reply().ok()?;
+    if attributes.map_state != MapState::VIEWABLE {
+        return None;
+    }
+    // 排除最小化的窗口
+    let state = get_u32_property(conn, window, atoms._NET_WM_STATE, AtomEnum::ATOM)
+        .unwrap_or_default();
+    if state.contains(&atoms._NET_WM_STATE_HIDDEN) {
+        return None;
+    }
+    // 排除桌面窗口，相当于 Windows 的 Program Manager
+    let window_type = get_u32_property(conn, window, atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM)
+        .unwrap_or_default();
+    if window_type.contains(&atoms._NET_WM_WINDOW_TYPE_DESKTOP) {
+        return None;
+    }
+
+    let geometry = conn.get_geometry(window).ok()?.reply().ok()?;
+    if geometry.width == 0 || geometry.height == 0 {
+        return None;
+    }
+    let origin = conn
+        .translate_coordinates(window, root, 0, 0)
+        .ok()?
+        .reply()
+        .ok()?;
+
+    // 加上窗口管理器绘制的边框，保证高亮区域包含标题栏: [left, right, top, bottom]
+    let extents = get_u32_property(conn, window, atoms._NET_FRAME_EXTENTS, AtomEnum::CARDINAL)
+        .filter(|extents| extents.len() == 4)
+        .unwrap_or_else(|| vec![0; 4]);
+    let (left, right, top, bottom) = (extents[0], extents[1], extents[2], extents[3]);
+
+    Some(WindowInfo {
+        name: window_name(conn, atoms, window),
+        bounds: Rect {
+            x: origin.dst_x as f64 - left as f64,
+            y: origin.dst_y as f64 - top as f64,
+            width: (geometry.width as u32 + left + right) as f64,
+            height: (geometry.height as u32 + top + bottom) as f64,
+        },
+    })
+}
+
+/// 优先读取 UTF-8 的 _NET_WM_NAME，没有时回退到 WM_NAME
+fn window_name(conn: &RustConnection, atoms: &Atoms, window: Window) -> String {
+    let read = |property: u32, ty: u32| {
+        conn.get_property(false, window, property, ty, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()
+            .filter(|reply| !reply.value.is_empty())
+            .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+    };
+    read(atoms._NET_WM_NAME, atoms.UTF8_STRING)
+        .or_else(|| read(AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()))
+        .unwrap_or_default()
+}
+
+fn monitor_infos(conn: &RustConnection, root: Window) -> Vec<WindowInfo> {
+    let Some(reply) = conn
+        .randr_get_monitors(root, true)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+    else {
+        log::error!("RRGetMonitors failed");
+        return vec![];
+    };
+    reply
+        .monitors
+        .iter()
+        .map(|monitor| {
+            let t = if monitor.primary {
+                "Primary".to_string()
+            } else {
+                format!("l{}t{}", monitor.x, monitor.y)
+            };
+            WindowInfo {
+                name: format!("Monitor_{}", t),
+                bounds: Rect {
+                    x: monitor.x as f64,
+                    y: monitor.y as f64,
+                    width: monitor.width as f64,
+                    height: monitor.height as f64,
+                },
+            }
+        })
+        .collect()
+}
+
+fn get_u32_property(
+    conn: &RustConnection,
+    window: Window,
+    property: u32,
+    ty: AtomEnum,
+) -> Option<Vec<u32>> {
+    let reply = conn
+        .get_property(false, window, property, ty, 0, u32::MAX)
+        .ok()?
+        .reply()
+        .ok()?;
+    Some(reply.value32()?.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enumerate_windows() {
+        let windows = enumerate_windows();
+        println!("windows: {:#?}", windows);
+    }
+}
@@ -8,4 +8,13 @@ pub enum CaptureError {
     FailedToCaptureImage,
     FailedToGetRGBAData,
     FailedToGetBuffer,
+    FailedToConnectDisplay,
+    UnsupportedPixelFormat,
+    FailedToCallPortal,
+    PortalTimeout,
+    FailedToLoadImage,
+    FailedToEncodeImage,
+    FailedToEnumerateWindows,
+    FailedToLoadWindows,
+    RegionOutOfBounds,
 }
\ No newline at end of file
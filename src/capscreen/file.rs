@@ -0,0 +1,135 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::capscreen::{
+    CaptureError, Frame,
+    backend::{Capabilities, CaptureBackend, MonitorInfo, crop_region},
+    enumerate::{Rect, WindowInfo},
+};
+
+/// 从文件加载截图和窗口列表，用于没有屏幕的 CI 环境，保证 /bg 和 /windows 的结果可复现
+/// 整张图片视为一个显示器组成的虚拟桌面，窗口坐标与图片像素一致
+pub struct FileBackend {
+    frame: Frame,
+    windows: Vec<WindowInfo>,
+    source: PathBuf,
+}
+
+impl FileBackend {
+    /// `frame_path` 为 PNG 文件，`windows_path` 为 WindowInfo 数组的 JSON 文件
+    pub fn new(frame_path: &Path, windows_path: Option<&Path>) -> Result<Self, CaptureError> {
+        let frame = Frame::from_png(frame_path)?;
+        let windows = match windows_path {
+            Some(path) => load_windows(path)?,
+            None => vec![],
+        };
+        Ok(Self {
+            frame,
+            windows,
+            source: frame_path.to_path_buf(),
+        })
+    }
+
+    fn desktop(&self) -> Rect {
+        Rect {
+            x: 0.0,
+            y: 0.0,
+            width: self.frame.width as f64,
+            height: self.frame.height as f64,
+        }
+    }
+}
+
+impl CaptureBackend for FileBackend {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            virtual_desktop: true,
+            windows: true,
+        }
+    }
+
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>, CaptureError> {
+        Ok(vec![MonitorInfo {
+            id: 0,
+            name: self.source.to_string_lossy().into_owned(),
+            bounds: self.desktop(),
+            scale_factor: 1.0,
+            is_primary: true,
+        }])
+    }
+
+    fn capture_monitor(&self, monitor_id: u32) -> Result<Frame, CaptureError> {
+        if monitor_id != 0 {
+            log::error!("Failed to find monitor with id: {}", monitor_id);
+            return Err(CaptureError::FailedToFindDisplay);
+        }
+        Ok(self.frame.clone())
+    }
+
+    fn capture_region(&self, region: &Rect) -> Result<Frame, CaptureError> {
+        crop_region(&self.frame, &self.desktop(), region)
+    }
+
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>, CaptureError> {
+        Ok(self.windows.clone())
+    }
+
+    fn capture_desktop(&self) -> Result<Frame, CaptureError> {
+        Ok(self.frame.clone())
+    }
+}
+
+fn load_windows(path: &Path) -> Result<Vec<WindowInfo>, CaptureError> {
+    let file = File::open(path).map_err(|e| {
+        log::error!("Failed to open windows {:?}: {}", path, e);
+        CaptureError::FailedToLoadWindows
+    })?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+        log::error!("Failed to parse windows {:?}: {}", path, e);
+        CaptureError::FailedToLoadWindows
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_backend() {
+        let dir = std::env::temp_dir();
+        let frame_path = dir.join(format!("quickcap-file-{}.png", std::process::id()));
+        let windows_path = dir.join(format!("quickcap-file-{}.json", std::process::id()));
+
+        let file = File::create(&frame_path).unwrap();
+        let mut encoder = png::Encoder::new(file, 2, 2);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[1u8; 16]).unwrap();
+        writer.finish().unwrap();
+        std::fs::write(
+            &windows_path,
+            r#"[{"name":"Editor","bounds":{"x":0,"y":0,"width":1,"height":2}}]"#,
+        )
+        .unwrap();
+
+        let backend = FileBackend::new(&frame_path, Some(&windows_path)).unwrap();
+        let frame = backend.capture_desktop().unwrap();
+        assert_eq!((frame.width, frame.height), (2, 2));
+        let windows = backend.enumerate_windows().unwrap();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].name, "Editor");
+        let region = backend.capture_region(&windows[0].bounds).unwrap();
+        assert_eq!((region.width, region.height), (1, 2));
+
+        std::fs::remove_file(frame_path).unwrap();
+        std::fs::remove_file(windows_path).unwrap();
+    }
+}
@@ -1,7 +1,77 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use png::{ColorType, Decoder, Transformations};
+
+use crate::capscreen::CaptureError;
 
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct Frame {
     pub data: Vec<u8>,
     pub width: u32,
     pub height: u32,
-}
\ No newline at end of file
+}
+
+impl Frame {
+    /// 读取PNG文件并统一转换为RGBA8
+    pub fn from_png(path: &Path) -> Result<Frame, CaptureError> {
+        let file = File::open(path).map_err(|e| {
+            log::error!("Failed to open image {:?}: {}", path, e);
+            CaptureError::FailedToLoadImage
+        })?;
+        let mut decoder = Decoder::new(BufReader::new(file));
+        decoder
+            .set_transformations(Transformations::normalize_to_color8() | Transformations::ALPHA);
+        let mut reader = decoder.read_info().map_err(|e| {
+            log::error!("Failed to decode image {:?}: {}", path, e);
+            CaptureError::FailedToLoadImage
+        })?;
+        let mut buf = vec![0u8; reader.output_buffer_size().unwrap_or_default()];
+        let info = reader.next_frame(&mut buf).map_err(|e| {
+            log::error!("Failed to decode image {:?}: {}", path, e);
+            CaptureError::FailedToLoadImage
+        })?;
+        buf.truncate(info.buffer_size());
+
+        let data = match info.color_type {
+            ColorType::Rgba => buf,
+            ColorType::Rgb => buf
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            ColorType::Indexed => {
+                log::error!("Unexpected indexed image after expand: {:?}", path);
+                return Err(CaptureError::FailedToLoadImage);
+            }
+        };
+        Ok(Frame {
+            data,
+            width: info.width,
+            height: info.height,
+        })
+    }
+
+    /// 裁剪出指定的像素区域，超出部分会被截断
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Frame {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let row_len = width as usize * 4;
+        let mut data = Vec::with_capacity(row_len * height as usize);
+        for row in y..y + height {
+            let offset = (row as usize * self.width as usize + x as usize) * 4;
+            data.extend_from_slice(&self.data[offset..offset + row_len]);
+        }
+        Frame {
+            data,
+            width,
+            height,
+        }
+    }
+}
@@ -1,7 +1,13 @@
-use crate::capscreen::{error::CaptureError, frame::Frame};
+use crate::capscreen::{
+    backend::{CaptureBackend, Capabilities, MonitorInfo, crop_region},
+    enumerate::{self, Rect, WindowInfo},
+    error::CaptureError,
+    frame::Frame,
+};
 use objc2::rc::Retained;
 use objc2::runtime::AnyObject;
 use objc2_app_kit::{NSScreenSaverWindowLevel, NSWindow, NSWindowCollectionBehavior};
+use objc2_core_graphics::CGMainDisplayID;
 use screencapturekit::{
     prelude::{CGDisplay, PixelFormat, SCContentFilter, SCShareableContent, SCStreamConfiguration},
     screenshot_manager::capture_image_with_stream,
@@ -9,6 +15,83 @@ use screencapturekit::{
 use tao::platform::macos::WindowExtMacOS;
 use tao::window::Window;
 
+/// ScreenCaptureKit 按显示器截图，坐标为全局逻辑坐标(点)，每个显示器一个蒙层窗口
+pub struct MacosBackend;
+
+impl CaptureBackend for MacosBackend {
+    fn name(&self) -> &'static str {
+        "screencapturekit"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            virtual_desktop: false,
+            windows: true,
+        }
+    }
+
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>, CaptureError> {
+        let content = SCShareableContent::get().map_err(|_| {
+            log::error!("Failed to get shareable content");
+            CaptureError::FailedToGetShareableContent
+        })?;
+        let main_display_id = CGMainDisplayID();
+        Ok(content
+            .displays()
+            .into_iter()
+            .map(|display| {
+                let id = display.display_id();
+                let frame = display.frame();
+                let scale_factor = CGDisplay::new(id)
+                    .display_mode()
+                    .filter(|mode| mode.logical_width() > 0)
+                    .map(|mode| mode.pixel_width() as f64 / mode.logical_width() as f64)
+                    .unwrap_or(1.0);
+                MonitorInfo {
+                    id,
+                    name: format!("Display {}", id),
+                    bounds: Rect {
+                        x: frame.origin().x,
+                        y: frame.origin().y,
+                        width: frame.size().width,
+                        height: frame.size().height,
+                    },
+                    scale_factor,
+                    is_primary: id == main_display_id,
+                }
+            })
+            .collect())
+    }
+
+    fn capture_monitor(&self, monitor_id: u32) -> Result<Frame, CaptureError> {
+        capscreen(monitor_id)
+    }
+
+    /// 区域必须位于单个显示器内，截取该显示器后再裁剪
+    fn capture_region(&self, region: &Rect) -> Result<Frame, CaptureError> {
+        let monitors = self.list_monitors()?;
+        let monitor = monitors
+            .iter()
+            .find(|monitor| {
+                let bounds = &monitor.bounds;
+                region.x >= bounds.x
+                    && region.x < bounds.x + bounds.width
+                    && region.y >= bounds.y
+                    && region.y < bounds.y + bounds.height
+            })
+            .ok_or_else(|| {
+                log::error!("Region {:?} is not on any display", region);
+                CaptureError::RegionOutOfBounds
+            })?;
+        let frame = capscreen(monitor.id)?;
+        crop_region(&frame, &monitor.bounds, region)
+    }
+
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>, CaptureError> {
+        enumerate::macos::enumerate_all_windows().ok_or(CaptureError::FailedToGetShareableContent)
+    }
+}
+
 pub fn capscreen(display_id: u32) -> Result<Frame, CaptureError> {
     let content = SCShareableContent::get().map_err(|_| {
         log::error!("Failed to get shareable content");
@@ -1,31 +1,57 @@
+pub mod backend;
+pub mod encode;
 pub mod enumerate;
 
 mod error;
+mod file;
 mod frame;
 
+pub use backend::{CaptureBackend, Capabilities, MonitorInfo};
 pub use error::CaptureError;
+pub use file::FileBackend;
 pub use frame::Frame;
-use tao::{monitor::MonitorHandle, window::Window};
+use tao::window::Window;
 
 #[cfg(target_os = "macos")]
 pub mod macos;
 #[cfg(target_os = "windows")]
 pub mod windows;
+#[cfg(target_os = "linux")]
+pub mod portal;
+#[cfg(target_os = "linux")]
+pub mod wayland;
+#[cfg(target_os = "linux")]
+pub mod wlroots;
+#[cfg(target_os = "linux")]
+pub mod x11;
 
-#[allow(unused)]
-pub fn capscreen(handle: &MonitorHandle) -> Result<Frame, CaptureError> {
+/// 当前平台的默认截图后端
+pub fn default_backend() -> Box<dyn CaptureBackend> {
     #[cfg(target_os = "macos")]
     {
-        use tao::platform::macos::MonitorHandleExtMacOS;
-
-        return macos::capscreen(handle.native_id());
+        Box::new(macos::MacosBackend)
+    }
+    #[cfg(target_os = "windows")]
+    {
+        Box::new(windows::WindowsBackend)
     }
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(target_os = "linux")]
     {
-        return windows::capscreen();
+        if is_wayland_session() {
+            Box::new(wayland::WaylandBackend)
+        } else {
+            Box::new(x11::X11Backend)
+        }
     }
 }
 
+/// 根据 WAYLAND_DISPLAY/XDG_SESSION_TYPE 判断当前是否为 Wayland 会话
+#[cfg(target_os = "linux")]
+pub fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+}
+
 #[allow(unused)]
 pub fn configure_overlay_window(window: &Window) {
     log::error!("configure_overlay_window");
@@ -33,8 +59,12 @@ pub fn configure_overlay_window(window: &Window) {
     {
         macos::configure_overlay_window(window);
     }
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(target_os = "windows")]
     {
         window.set_always_on_top(true);
     }
+    #[cfg(target_os = "linux")]
+    {
+        x11::configure_overlay_window(window);
+    }
 }
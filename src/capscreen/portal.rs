@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+
+use zbus::{
+    blocking::{Connection, Proxy, proxy::Builder},
+    proxy::CacheProperties,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+use crate::capscreen::{CaptureError, Frame};
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREENSHOT_INTERFACE: &str = "org.freedesktop.portal.Screenshot";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+/// 首次截图时 portal 可能弹出授权对话框，留足用户确认的时间
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 每次请求递增，保证同一进程内的 handle_token 不重复
+static REQUEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// 通过 xdg-desktop-portal 的 Screenshot 接口截取整个桌面（非交互模式）
+/// Wayland 下无法直接读取其他客户端的画面，GNOME/KDE 只能走 portal
+pub fn capscreen() -> Result<Frame, CaptureError> {
+    let conn = Connection::session().map_err(|e| {
+        log::error!("Failed to connect to session bus: {}", e);
+        CaptureError::FailedToConnectDisplay
+    })?;
+
+    // 必须在调用前订阅 Response，否则可能错过信号
+    // Request 的路径由 sender 和 handle_token 决定
+    let token = format!(
+        "quickcap{}_{}",
+        std::process::id(),
+        REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let sender = conn
+        .unique_name()
+        .map(|name| name.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+    let request_path = format!("{}/request/{}/{}", PORTAL_PATH, sender, token);
+    let request = proxy(&conn, &request_path, REQUEST_INTERFACE)?;
+    let mut responses = request.receive_signal("Response").map_err(portal_error)?;
+
+    let options = HashMap::from([
+        ("handle_token", Value::from(token.as_str())),
+        ("interactive", Value::from(false)),
+        ("modal", Value::from(false)),
+    ]);
+    let screenshot = proxy(&conn, PORTAL_PATH, SCREENSHOT_INTERFACE)?;
+    let handle: OwnedObjectPath = screenshot
+        .call("Screenshot", &("", options))
+        .map_err(portal_error)?;
+    if handle.as_str() != request_path {
+        log::error!(
+            "Portal returned unexpected request handle: {}",
+            handle.as_str()
+        );
+        return Err(CaptureError::FailedToCaptureImage);
+    }
+
+    // 阻塞迭代器没有超时，在线程中等待信号，portal 无响应时不会一直卡住
+    let (response_sender, response_receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = response_sender.send(responses.next());
+    });
+    let message = match response_receiver.recv_timeout(RESPONSE_TIMEOUT) {
+        Ok(Some(message)) => message,
+        Ok(None) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+            log::error!("Portal request closed without response");
+            return Err(CaptureError::FailedToCaptureImage);
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            log::error!("Portal did not respond within {:?}", RESPONSE_TIMEOUT);
+            return Err(CaptureError::PortalTimeout);
+        }
+    };
+    let (response, results): (u32, HashMap<String, OwnedValue>) =
+        message.body().deserialize().map_err(portal_error)?;
+    // 0: 成功, 1: 用户取消, 2: 其他错误
+    if response != 0 {
+        log::error!("Portal screenshot failed, response: {}", response);
+        return Err(CaptureError::FailedToCaptureImage);
+    }
+    let uri = results
+        .get("uri")
+        .and_then(|value| String::try_from(value.clone()).ok())
+        .ok_or_else(|| {
+            log::error!("Portal response has no uri: {:?}", results);
+            CaptureError::FailedToCaptureImage
+        })?;
+    let path = file_uri_to_path(&uri).ok_or_else(|| {
+        log::error!("Unsupported screenshot uri: {}", uri);
+        CaptureError::FailedToLoadImage
+    })?;
+
+    let frame = Frame::from_png(&path);
+    // portal 生成的是一次性文件，读取后删除，避免在用户目录中堆积
+    if let Err(e) = std::fs::remove_file(&path) {
+        log::error!("Failed to remove portal screenshot {:?}: {}", path, e);
+    }
+    frame
+}
+
+fn proxy<'a>(
+    conn: &Connection,
+    path: &'a str,
+    interface: &'a str,
+) -> Result<Proxy<'a>, CaptureError> {
+    Builder::new(conn)
+        .destination(PORTAL_DESTINATION)
+        .and_then(|builder| builder.path(path))
+        .and_then(|builder| builder.interface(interface))
+        .map(|builder| builder.cache_properties(CacheProperties::No))
+        .and_then(|builder| builder.build())
+        .map_err(portal_error)
+}
+
+fn portal_error(e: zbus::Error) -> CaptureError {
+    log::error!("Portal call failed: {}", e);
+    CaptureError::FailedToCallPortal
+}
+
+/// 将 file:// URI 转换为本地路径，处理百分号编码
+fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(decoded).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::{blocking::connection, interface, object_server::SignalEmitter};
+
+    /// 模拟 portal 服务，返回一张预先写好的 PNG
+    struct MockPortal {
+        uri: String,
+    }
+
+    #[interface(name = "org.freedesktop.portal.Screenshot")]
+    impl MockPortal {
+        async fn screenshot(
+            &self,
+            #[zbus(header)] header: zbus::message::Header<'_>,
+            #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+            _parent_window: &str,
+            options: HashMap<String, OwnedValue>,
+        ) -> OwnedObjectPath {
+            let token = String::try_from(options["handle_token"].clone()).unwrap();
+            let sender = header.sender().unwrap().as_str();
+            let path = format!(
+                "{}/request/{}/{}",
+                PORTAL_PATH,
+                sender.trim_start_matches(':').replace('.', "_"),
+                token
+            );
+            let results = HashMap::from([("uri", Value::from(self.uri.as_str()))]);
+            emitter
+                .connection()
+                .emit_signal(
+                    Some(sender),
+                    path.as_str(),
+                    REQUEST_INTERFACE,
+                    "Response",
+                    &(0u32, results),
+                )
+                .await
+                .unwrap();
+            OwnedObjectPath::try_from(path).unwrap()
+        }
+    }
+
+    /// 会在会话总线上注册 portal 的名字，默认跳过，需要在私有会话总线中运行:
+    /// `dbus-run-session -- cargo test portal -- --ignored`
+    #[test]
+    #[ignore = "requires a private session bus"]
+    fn test_capscreen_with_mock_portal() {
+        let path = std::env::temp_dir().join(format!("quickcap portal {}.png", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(file, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0, 0, 255, 0]).unwrap();
+        writer.finish().unwrap();
+
+        let uri = format!("file://{}", path.to_string_lossy().replace(' ', "%20"));
+        let _server = connection::Builder::session()
+            .unwrap()
+            .name(PORTAL_DESTINATION)
+            .unwrap()
+            .serve_at(PORTAL_PATH, MockPortal { uri })
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let frame = capscreen().expect("capscreen failed");
+        assert_eq!((frame.width, frame.height), (2, 1));
+        assert_eq!(frame.data, vec![255, 0, 0, 255, 0, 255, 0, 255]);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::capscreen::{
+    CaptureError, Frame,
+    backend::{Capabilities, CaptureBackend, MonitorInfo, crop_region, virtual_bounds},
+    enumerate::{Rect, WindowInfo},
+    portal, wlroots,
+};
+
+/// Wayland 会话优先使用 wlroots 原生协议，不支持时回退到 xdg-desktop-portal
+/// 坐标为合成器的逻辑坐标，Wayland 不允许读取其他客户端的窗口位置，因此不支持窗口感知
+pub struct WaylandBackend;
+
+impl WaylandBackend {
+    /// 截取整个桌面，同时返回 Frame 覆盖的逻辑区域
+    fn capture_with_area(&self) -> Result<(Frame, Rect), CaptureError> {
+        // wlroots 系合成器(sway/Hyprland)通常没有 portal，优先使用原生协议
+        let frame = wlroots::capscreen().or_else(|e| {
+            log::error!("wlroots capture unavailable, fallback to portal: {:?}", e);
+            portal::capscreen()
+        })?;
+        // portal 无法获取输出布局，认为 Frame 即整个桌面
+        let area = wlroots::list_monitors()
+            .map(|monitors| virtual_bounds(&monitors))
+            .unwrap_or(Rect {
+                x: 0.0,
+                y: 0.0,
+                width: frame.width as f64,
+                height: frame.height as f64,
+            });
+        Ok((frame, area))
+    }
+}
+
+impl CaptureBackend for WaylandBackend {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            virtual_desktop: true,
+            windows: false,
+        }
+    }
+
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>, CaptureError> {
+        wlroots::list_monitors()
+    }
+
+    fn capture_monitor(&self, monitor_id: u32) -> Result<Frame, CaptureError> {
+        wlroots::capture_output(monitor_id as usize).or_else(|e| {
+            log::error!("wlroots capture unavailable, fallback to portal: {:?}", e);
+            let monitor = self
+                .list_monitors()?
+                .into_iter()
+                .find(|monitor| monitor.id == monitor_id)
+                .ok_or(CaptureError::FailedToFindDisplay)?;
+            self.capture_region(&monitor.bounds)
+        })
+    }
+
+    fn capture_region(&self, region: &Rect) -> Result<Frame, CaptureError> {
+        let (frame, area) = self.capture_with_area()?;
+        crop_region(&frame, &area, region)
+    }
+
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>, CaptureError> {
+        Ok(vec![])
+    }
+
+    fn capture_desktop(&self) -> Result<Frame, CaptureError> {
+        self.capture_with_area().map(|(frame, _)| frame)
+    }
+}
@@ -1,28 +1,111 @@
 use rayon::prelude::*;
-use windows::Win32::{
-    Foundation::HWND,
-    Graphics::Gdi::{
-        BI_RGB, BITMAPINFO, BITMAPINFOHEADER, BitBlt, CreateCompatibleBitmap, CreateCompatibleDC,
-        DIB_RGB_COLORS, DeleteDC, DeleteObject, GetDC, GetDIBits, HGDIOBJ, ReleaseDC, SRCCOPY,
-        SelectObject,
-    },
-    UI::WindowsAndMessaging::{
-        GetSystemMetrics, SetWindowPos, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
-        SM_YVIRTUALSCREEN, SWP_NOZORDER,
+use windows::{
+    Win32::{
+        Foundation::{HWND, LPARAM, RECT},
+        Graphics::Gdi::{
+            BI_RGB, BITMAPINFO, BITMAPINFOHEADER, BitBlt, CreateCompatibleBitmap,
+            CreateCompatibleDC, DIB_RGB_COLORS, DeleteDC, DeleteObject, EnumDisplayMonitors, GetDC,
+            GetDIBits, GetMonitorInfoW, HDC, HGDIOBJ, HMONITOR, MONITORINFO, MONITORINFOEXW,
+            ReleaseDC, SRCCOPY, SelectObject,
+        },
+        UI::{
+            HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI},
+            WindowsAndMessaging::{
+                GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+                SM_YVIRTUALSCREEN, SWP_NOZORDER, SetWindowPos,
+            },
+        },
     },
+    core::BOOL,
 };
 use tao::window::Window;
 use tao::platform::windows::WindowExtWindows;
 
-use crate::capscreen::{CaptureError, Frame};
+use crate::capscreen::{
+    CaptureError, Frame,
+    backend::{CaptureBackend, Capabilities, MonitorInfo, virtual_bounds},
+    enumerate::{self, Rect, WindowInfo},
+};
+
+/// GDI 截取整个虚拟桌面，坐标系与 enumerate 一致，以虚拟桌面左上角为原点
+pub struct WindowsBackend;
+
+impl CaptureBackend for WindowsBackend {
+    fn name(&self) -> &'static str {
+        "gdi"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            virtual_desktop: true,
+            windows: true,
+        }
+    }
+
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>, CaptureError> {
+        Ok(list_monitors())
+    }
+
+    fn capture_monitor(&self, monitor_id: u32) -> Result<Frame, CaptureError> {
+        let monitor = list_monitors()
+            .into_iter()
+            .find(|monitor| monitor.id == monitor_id)
+            .ok_or_else(|| {
+                log::error!("Failed to find monitor with id: {}", monitor_id);
+                CaptureError::FailedToFindDisplay
+            })?;
+        self.capture_region(&monitor.bounds)
+    }
+
+    fn capture_region(&self, region: &Rect) -> Result<Frame, CaptureError> {
+        let desktop = virtual_bounds(&list_monitors());
+        if region.width <= 0.0
+            || region.height <= 0.0
+            || region.x < 0.0
+            || region.y < 0.0
+            || region.x + region.width > desktop.width
+            || region.y + region.height > desktop.height
+        {
+            log::error!("Region {:?} is out of virtual screen", region);
+            return Err(CaptureError::RegionOutOfBounds);
+        }
+        let (v_x, v_y) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+            )
+        };
+        capture_rect(
+            v_x + region.x as i32,
+            v_y + region.y as i32,
+            region.width as i32,
+            region.height as i32,
+        )
+    }
+
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>, CaptureError> {
+        enumerate::windows::enumerate_windows().ok_or(CaptureError::FailedToEnumerateWindows)
+    }
+
+    fn capture_desktop(&self) -> Result<Frame, CaptureError> {
+        capscreen()
+    }
+}
 
 pub fn capscreen() -> Result<Frame, CaptureError> {
     unsafe {
-        let x = GetSystemMetrics(SM_XVIRTUALSCREEN);
-        let y = GetSystemMetrics(SM_YVIRTUALSCREEN);
-        let width = GetSystemMetrics(SM_CXVIRTUALSCREEN);
-        let height = GetSystemMetrics(SM_CYVIRTUALSCREEN);
+        capture_rect(
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        )
+    }
+}
 
+/// 截取屏幕坐标下的矩形区域
+fn capture_rect(x: i32, y: i32, width: i32, height: i32) -> Result<Frame, CaptureError> {
+    unsafe {
         let h_screen_dc = GetDC(Some(HWND(std::ptr::null_mut())));
         let h_memory_dc = CreateCompatibleDC(Some(h_screen_dc));
 
@@ -95,6 +178,74 @@ pub fn capscreen() -> Result<Frame, CaptureError> {
     }
 }
 
+/// 枚举所有显示器，坐标转换为基于虚拟桌面原点的坐标，id 为枚举顺序
+pub fn list_monitors() -> Vec<MonitorInfo> {
+    let mut monitors: Vec<MonitorInfo> = vec![];
+    unsafe {
+        _ = EnumDisplayMonitors(
+            None,
+            None,
+            Some(monitor_enum_proc),
+            LPARAM(&mut monitors as *mut _ as isize),
+        );
+    }
+    let (v_x, v_y) = unsafe {
+        (
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+        )
+    };
+    for monitor in monitors.iter_mut() {
+        monitor.bounds.x -= v_x as f64;
+        monitor.bounds.y -= v_y as f64;
+    }
+    monitors
+}
+
+unsafe extern "system" fn monitor_enum_proc(
+    hmonitor: HMONITOR,
+    _: HDC,
+    _: *mut RECT,
+    lparam: LPARAM,
+) -> BOOL {
+    unsafe {
+        let mut monitor_info = MONITORINFOEXW {
+            monitorInfo: MONITORINFO {
+                cbSize: core::mem::size_of::<MONITORINFOEXW>() as u32,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        if !GetMonitorInfoW(hmonitor, &mut monitor_info.monitorInfo).as_bool() {
+            log::error!("GetMonitorInfoW failed,hmonitor: {hmonitor:?}");
+            return true.into();
+        }
+        let Some(monitors) = (lparam.0 as *mut Vec<MonitorInfo>).as_mut() else {
+            return true.into();
+        };
+        let (mut dpi_x, mut dpi_y) = (96, 96);
+        if let Err(e) = GetDpiForMonitor(hmonitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) {
+            log::error!("GetDpiForMonitor failed,hmonitor: {hmonitor:?} error: {:?}", e);
+        }
+        let device = &monitor_info.szDevice;
+        let len = device.iter().position(|&c| c == 0).unwrap_or(device.len());
+        let rc = monitor_info.monitorInfo.rcMonitor;
+        monitors.push(MonitorInfo {
+            id: monitors.len() as u32,
+            name: String::from_utf16_lossy(&device[..len]),
+            bounds: Rect {
+                x: rc.left as f64,
+                y: rc.top as f64,
+                width: (rc.right - rc.left) as f64,
+                height: (rc.bottom - rc.top) as f64,
+            },
+            scale_factor: dpi_x as f64 / 96.0,
+            is_primary: (monitor_info.monitorInfo.dwFlags & 1) != 0,
+        });
+    }
+    true.into()
+}
+
 pub fn set_window_pos(window: &Window) {
     unsafe {
         // 获取虚拟屏幕的位置和大小
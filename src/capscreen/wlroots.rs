@@ -0,0 +1,673 @@
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum, delegate_noop,
+    globals::{GlobalList, GlobalListContents, registry_queue_init},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_output::{self, WlOutput},
+        wl_registry::WlRegistry,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+    },
+};
+use wayland_protocols::{
+    ext::{
+        image_capture_source::v1::client::{
+            ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+            ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1,
+        },
+        image_copy_capture::v1::client::{
+            ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1},
+            ext_image_copy_capture_manager_v1::{ExtImageCopyCaptureManagerV1, Options},
+            ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+        },
+    },
+    xdg::xdg_output::zv1::client::{
+        zxdg_output_manager_v1::ZxdgOutputManagerV1,
+        zxdg_output_v1::{self, ZxdgOutputV1},
+    },
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use crate::capscreen::{
+    CaptureError, Frame,
+    backend::MonitorInfo,
+    enumerate::Rect,
+};
+
+/// 通过 ext-image-copy-capture 或 wlr-screencopy 逐个复制 wl_output，
+/// 再按输出布局拼接为一张覆盖整个虚拟桌面的 Frame
+/// 混合缩放时以最大缩放为画布比例，较低缩放的输出不做放大
+pub fn capscreen() -> Result<Frame, CaptureError> {
+    capture(None)
+}
+
+/// 只复制一个输出，index 与 list_monitors 返回的 id 一致
+pub fn capture_output(index: usize) -> Result<Frame, CaptureError> {
+    capture(Some(index))
+}
+
+/// 输出的逻辑布局，坐标与 xdg_output 一致
+pub fn list_monitors() -> Result<Vec<MonitorInfo>, CaptureError> {
+    let (_, _, state) = connect()?;
+    Ok(state
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(index, output)| MonitorInfo {
+            id: index as u32,
+            name: output.name.clone(),
+            bounds: Rect {
+                x: output.x as f64,
+                y: output.y as f64,
+                width: output.logical_width as f64,
+                height: output.logical_height as f64,
+            },
+            // 分数缩放时 wl_output 只会报告向上取整的整数缩放
+            scale_factor: if output.logical_width > 0 {
+                output.mode_width as f64 / output.logical_width as f64
+            } else {
+                output.scale as f64
+            },
+            // Wayland 没有主显示器的概念
+            is_primary: false,
+        })
+        .collect())
+}
+
+/// 连接合成器并读取所有输出的布局
+fn connect() -> Result<(GlobalList, EventQueue<State>, State), CaptureError> {
+    let conn = Connection::connect_to_env().map_err(|e| {
+        log::error!("Failed to connect to wayland display: {}", e);
+        CaptureError::FailedToConnectDisplay
+    })?;
+    let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(|e| {
+        log::error!("Failed to init wayland registry: {}", e);
+        CaptureError::FailedToConnectDisplay
+    })?;
+    let qh = queue.handle();
+
+    let mut state = State::default();
+    let xdg_output_manager = globals
+        .bind::<ZxdgOutputManagerV1, _, _>(&qh, 2..=3, ())
+        .ok();
+    let output_globals = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|global| global.interface == WlOutput::interface().name)
+            .map(|global| (global.name, global.version))
+            .collect::<Vec<_>>()
+    });
+    for (index, (name, version)) in output_globals.into_iter().enumerate() {
+        let output: WlOutput = globals.registry().bind(name, version.min(4), &qh, index);
+        if let Some(manager) = &xdg_output_manager {
+            manager.get_xdg_output(&output, &qh, index);
+        }
+        state.outputs.push(OutputInfo::new(output));
+    }
+    roundtrip(&mut queue, &mut state)?;
+    if state.outputs.is_empty() {
+        log::error!("No wayland output found");
+        return Err(CaptureError::FailedToFindDisplay);
+    }
+    Ok((globals, queue, state))
+}
+
+fn capture(only: Option<usize>) -> Result<Frame, CaptureError> {
+    let (globals, mut queue, mut state) = connect()?;
+    let qh = queue.handle();
+
+    let shm: WlShm = globals.bind(&qh, 1..=1, ()).map_err(|e| {
+        log::error!("wl_shm not available: {}", e);
+        CaptureError::FailedToGetBuffer
+    })?;
+    let ext = globals
+        .bind::<ExtImageCopyCaptureManagerV1, _, _>(&qh, 1..=1, ())
+        .ok()
+        .zip(
+            globals
+                .bind::<ExtOutputImageCaptureSourceManagerV1, _, _>(&qh, 1..=1, ())
+                .ok(),
+        );
+    let wlr = globals
+        .bind::<ZwlrScreencopyManagerV1, _, _>(&qh, 1..=3, ())
+        .ok();
+    if ext.is_none() && wlr.is_none() {
+        log::error!("Compositor supports neither ext-image-copy-capture nor wlr-screencopy");
+        return Err(CaptureError::UnsupportedPlatform);
+    }
+
+    let indices = match only {
+        Some(index) if index < state.outputs.len() => vec![index],
+        Some(index) => {
+            log::error!("Failed to find output with index: {}", index);
+            return Err(CaptureError::FailedToFindDisplay);
+        }
+        None => (0..state.outputs.len()).collect(),
+    };
+    let mut captures = vec![];
+    for index in indices {
+        let output = state.outputs[index].output.clone();
+        let frame = match (&ext, &wlr) {
+            (Some((manager, source_manager)), _) => capture_output_ext(
+                &mut queue,
+                &mut state,
+                &shm,
+                manager,
+                source_manager,
+                &output,
+            ),
+            (None, Some(manager)) => {
+                capture_output_wlr(&mut queue, &mut state, &shm, manager, &output)
+            }
+            (None, None) => unreachable!(),
+        }?;
+        captures.push((index, frame));
+    }
+    Ok(stitch(&state.outputs, captures))
+}
+
+struct OutputInfo {
+    output: WlOutput,
+    name: String,
+    // 逻辑坐标，优先使用 xdg_output 提供的值
+    x: i32,
+    y: i32,
+    logical_width: i32,
+    logical_height: i32,
+    mode_width: i32,
+    mode_height: i32,
+    scale: i32,
+}
+
+impl OutputInfo {
+    fn new(output: WlOutput) -> Self {
+        Self {
+            output,
+            name: String::new(),
+            x: 0,
+            y: 0,
+            logical_width: 0,
+            logical_height: 0,
+            mode_width: 0,
+            mode_height: 0,
+            scale: 1,
+        }
+    }
+}
+
+/// 当前正在复制的输出的缓冲区参数和进度
+#[derive(Default)]
+struct CaptureProgress {
+    format: Option<wl_shm::Format>,
+    width: u32,
+    height: u32,
+    stride: u32,
+    y_invert: bool,
+    buffer_done: bool,
+    ready: bool,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct State {
+    outputs: Vec<OutputInfo>,
+    capture: CaptureProgress,
+}
+
+/// 支持的 32 位 shm 格式，ARGB/XRGB 小端内存布局为 BGRA，ABGR/XBGR 为 RGBA
+fn is_supported_format(format: wl_shm::Format) -> bool {
+    matches!(
+        format,
+        wl_shm::Format::Argb8888
+            | wl_shm::Format::Xrgb8888
+            | wl_shm::Format::Abgr8888
+            | wl_shm::Format::Xbgr8888
+    )
+}
+
+fn capture_output_ext(
+    queue: &mut EventQueue<State>,
+    state: &mut State,
+    shm: &WlShm,
+    manager: &ExtImageCopyCaptureManagerV1,
+    source_manager: &ExtOutputImageCaptureSourceManagerV1,
+    output: &WlOutput,
+) -> Result<Frame, CaptureError> {
+    let qh = queue.handle();
+    state.capture = CaptureProgress::default();
+    let source = source_manager.create_source(output, &qh, ());
+    let session = manager.create_session(&source, Options::empty(), &qh, ());
+    while !state.capture.buffer_done && !state.capture.failed {
+        blocking_dispatch(queue, state)?;
+    }
+    let result = if state.capture.failed {
+        log::error!("ext-image-copy-capture session stopped");
+        Err(CaptureError::FailedToCaptureImage)
+    } else {
+        state.capture.stride = state.capture.width * 4;
+        ShmBuffer::new(shm, &qh, &state.capture).and_then(|buffer| {
+            let frame = session.create_frame(&qh, ());
+            frame.attach_buffer(&buffer.buffer);
+            frame.damage_buffer(
+                0,
+                0,
+                state.capture.width as i32,
+                state.capture.height as i32,
+            );
+            frame.capture();
+            while !state.capture.ready && !state.capture.failed {
+                blocking_dispatch(queue, state)?;
+            }
+            frame.destroy();
+            buffer.into_frame(&state.capture)
+        })
+    };
+    session.destroy();
+    source.destroy();
+    result
+}
+
+fn capture_output_wlr(
+    queue: &mut EventQueue<State>,
+    state: &mut State,
+    shm: &WlShm,
+    manager: &ZwlrScreencopyManagerV1,
+    output: &WlOutput,
+) -> Result<Frame, CaptureError> {
+    let qh = queue.handle();
+    state.capture = CaptureProgress::default();
+    let frame = manager.capture_output(0, output, &qh, ());
+    // v3 之前没有 buffer_done 事件，buffer 事件在 capture_output 后立即发送，一次 roundtrip 即可
+    if manager.version() >= 3 {
+        while !state.capture.buffer_done && !state.capture.failed {
+            blocking_dispatch(queue, state)?;
+        }
+    } else {
+        roundtrip(queue, state)?;
+    }
+    let result = if state.capture.failed || state.capture.format.is_none() {
+        log::error!("wlr-screencopy has no usable shm buffer");
+        Err(CaptureError::FailedToCaptureImage)
+    } else {
+        ShmBuffer::new(shm, &qh, &state.capture).and_then(|buffer| {
+            frame.copy(&buffer.buffer);
+            while !state.capture.ready && !state.capture.failed {
+                blocking_dispatch(queue, state)?;
+            }
+            buffer.into_frame(&state.capture)
+        })
+    };
+    frame.destroy();
+    result
+}
+
+/// 按输出的逻辑布局拼接各个输出的画面
+fn stitch(outputs: &[OutputInfo], captures: Vec<(usize, Frame)>) -> Frame {
+    let min_x = captures.iter().map(|(i, _)| outputs[*i].x).min().unwrap_or(0);
+    let min_y = captures.iter().map(|(i, _)| outputs[*i].y).min().unwrap_or(0);
+    let scale = captures
+        .iter()
+        .map(|(index, frame)| {
+            let logical_width = outputs[*index].logical_width.max(1);
+            frame.width as f64 / logical_width as f64
+        })
+        .fold(1.0, f64::max);
+
+    let placed = captures
+        .into_iter()
+        .map(|(index, frame)| {
+            let output = &outputs[index];
+            let x = ((output.x - min_x) as f64 * scale).round() as usize;
+            let y = ((output.y - min_y) as f64 * scale).round() as usize;
+            log::error!(
+                "output {}: position ({}, {}), frame {}x{}",
+                output.name,
+                x,
+                y,
+                frame.width,
+                frame.height
+            );
+            (x, y, frame)
+        })
+        .collect::<Vec<_>>();
+    let width = placed
+        .iter()
+        .map(|(x, _, frame)| x + frame.width as usize)
+        .max()
+        .unwrap_or(0);
+    let height = placed
+        .iter()
+        .map(|(_, y, frame)| y + frame.height as usize)
+        .max()
+        .unwrap_or(0);
+
+    let mut data = vec![0u8; width * height * 4];
+    for (x, y, frame) in placed {
+        let row_len = frame.width as usize * 4;
+        for (row, src) in frame.data.chunks_exact(row_len).enumerate() {
+            let offset = ((y + row) * width + x) * 4;
+            data[offset..offset + row_len].copy_from_slice(src);
+        }
+    }
+    Frame {
+        data,
+        width: width as u32,
+        height: height as u32,
+    }
+}
+
+/// memfd 支撑的 wl_shm 缓冲区
+struct ShmBuffer {
+    buffer: WlBuffer,
+    pool: WlShmPool,
+    addr: *mut libc::c_void,
+    size: usize,
+    _fd: OwnedFd,
+}
+
+impl ShmBuffer {
+    fn new(
+        shm: &WlShm,
+        qh: &QueueHandle<State>,
+        capture: &CaptureProgress,
+    ) -> Result<Self, CaptureError> {
+        let format = capture
+            .format
+            .filter(|format| is_supported_format(*format))
+            .ok_or_else(|| {
+                log::error!("Unsupported shm format: {:?}", capture.format);
+                CaptureError::UnsupportedPixelFormat
+            })?;
+        let size = capture.stride as usize * capture.height as usize;
+        unsafe {
+            let raw_fd = libc::memfd_create(c"quickcap-screencopy".as_ptr(), libc::MFD_CLOEXEC);
+            if raw_fd < 0 {
+                log::error!("memfd_create failed");
+                return Err(CaptureError::FailedToGetBuffer);
+            }
+            let fd = OwnedFd::from_raw_fd(raw_fd);
+            if libc::ftruncate(raw_fd, size as libc::off_t) < 0 {
+                log::error!("ftruncate failed");
+                return Err(CaptureError::FailedToGetBuffer);
+            }
+            let addr = libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                raw_fd,
+                0,
+            );
+            if addr == libc::MAP_FAILED {
+                log::error!("mmap failed");
+                return Err(CaptureError::FailedToGetBuffer);
+            }
+            let pool = shm.create_pool(fd.as_fd(), size as i32, qh, ());
+            let buffer = pool.create_buffer(
+                0,
+                capture.width as i32,
+                capture.height as i32,
+                capture.stride as i32,
+                format,
+                qh,
+                (),
+            );
+            Ok(Self {
+                buffer,
+                pool,
+                addr,
+                size,
+                _fd: fd,
+            })
+        }
+    }
+
+    /// 复制完成后读取缓冲区，统一转换为 RGBA
+    fn into_frame(self, capture: &CaptureProgress) -> Result<Frame, CaptureError> {
+        if capture.failed || !capture.ready {
+            log::error!("Screencopy frame failed");
+            return Err(CaptureError::FailedToCaptureImage);
+        }
+        let src = unsafe { std::slice::from_raw_parts(self.addr as *const u8, self.size) };
+        let width = capture.width as usize;
+        let height = capture.height as usize;
+        let stride = capture.stride as usize;
+        let swap = matches!(
+            capture.format,
+            Some(wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888)
+        );
+
+        let mut data = Vec::with_capacity(width * height * 4);
+        for row in 0..height {
+            let src_row = if capture.y_invert {
+                height - 1 - row
+            } else {
+                row
+            };
+            let line = &src[src_row * stride..src_row * stride + width * 4];
+            for pixel in line.chunks_exact(4) {
+                if swap {
+                    data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+                } else {
+                    data.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+                }
+            }
+        }
+        Ok(Frame {
+            data,
+            width: capture.width,
+            height: capture.height,
+        })
+    }
+}
+
+impl Drop for ShmBuffer {
+    fn drop(&mut self) {
+        self.buffer.destroy();
+        self.pool.destroy();
+        unsafe {
+            libc::munmap(self.addr, self.size);
+        }
+    }
+}
+
+fn roundtrip(queue: &mut EventQueue<State>, state: &mut State) -> Result<(), CaptureError> {
+    queue.roundtrip(state).map(|_| ()).map_err(|e| {
+        log::error!("Wayland roundtrip failed: {}", e);
+        CaptureError::FailedToCaptureImage
+    })
+}
+
+fn blocking_dispatch(queue: &mut EventQueue<State>, state: &mut State) -> Result<(), CaptureError> {
+    queue.blocking_dispatch(state).map(|_| ()).map_err(|e| {
+        log::error!("Wayland dispatch failed: {}", e);
+        CaptureError::FailedToCaptureImage
+    })
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlOutput, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[*index];
+        match event {
+            // xdg_output 可用时以其逻辑坐标为准
+            wl_output::Event::Geometry { x, y, .. } if output.logical_width == 0 => {
+                output.x = x;
+                output.y = y;
+            }
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.mode_width = width;
+                output.mode_height = height;
+            }
+            wl_output::Event::Scale { factor } => output.scale = factor,
+            wl_output::Event::Name { name } => output.name = name,
+            wl_output::Event::Done if output.logical_width == 0 && output.scale > 0 => {
+                output.logical_width = output.mode_width / output.scale;
+                output.logical_height = output.mode_height / output.scale;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[*index];
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                output.x = x;
+                output.y = y;
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                output.logical_width = width;
+                output.logical_height = height;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let capture = &mut state.capture;
+        match event {
+            // 可能会收到多个 buffer 事件，保留第一个支持的格式
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format: WEnum::Value(format),
+                width,
+                height,
+                stride,
+            } if capture
+                .format
+                .is_none_or(|current| !is_supported_format(current)) =>
+            {
+                capture.format = Some(format);
+                capture.width = width;
+                capture.height = height;
+                capture.stride = stride;
+            }
+            zwlr_screencopy_frame_v1::Event::Flags {
+                flags: WEnum::Value(flags),
+            } => {
+                capture.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => capture.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => capture.ready = true,
+            zwlr_screencopy_frame_v1::Event::Failed => capture.failed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureSessionV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let capture = &mut state.capture;
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => {
+                capture.width = width;
+                capture.height = height;
+            }
+            ext_image_copy_capture_session_v1::Event::ShmFormat {
+                format: WEnum::Value(format),
+            } if is_supported_format(format) && capture.format.is_none() => {
+                capture.format = Some(format);
+            }
+            ext_image_copy_capture_session_v1::Event::Done => capture.buffer_done = true,
+            ext_image_copy_capture_session_v1::Event::Stopped => capture.failed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Ready => state.capture.ready = true,
+            ext_image_copy_capture_frame_v1::Event::Failed { reason } => {
+                log::error!("ext-image-copy-capture frame failed: {:?}", reason);
+                state.capture.failed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: ignore WlShmPool);
+delegate_noop!(State: ignore WlBuffer);
+delegate_noop!(State: ZxdgOutputManagerV1);
+delegate_noop!(State: ZwlrScreencopyManagerV1);
+delegate_noop!(State: ExtImageCopyCaptureManagerV1);
+delegate_noop!(State: ExtOutputImageCaptureSourceManagerV1);
+delegate_noop!(State: ExtImageCaptureSourceV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 需要 wlroots 合成器，默认跳过，可以在无头 sway 中运行:
+    /// `WLR_BACKENDS=headless WLR_RENDERER=pixman sway &`
+    /// `WAYLAND_DISPLAY=wayland-1 cargo test wlroots -- --ignored`
+    #[test]
+    #[ignore = "requires a wlroots compositor"]
+    fn test_capscreen() {
+        let frame = capscreen().expect("capscreen failed");
+        println!("frame: {}x{}", frame.width, frame.height);
+        assert_eq!(frame.data.len(), (frame.width * frame.height * 4) as usize);
+    }
+}
@@ -0,0 +1,371 @@
+use gtk::prelude::{GtkWindowExt, WidgetExt};
+use rayon::prelude::*;
+use tao::{platform::unix::WindowExtUnix, window::Window};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::ConnectionExt as _,
+        shm::ConnectionExt as _,
+        xproto::{ConnectionExt as _, ImageFormat, ImageOrder, Screen},
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::capscreen::{
+    CaptureError, Frame,
+    backend::{CaptureBackend, Capabilities, MonitorInfo},
+    enumerate::{self, Rect, WindowInfo},
+};
+
+/// X11 根窗口即虚拟桌面，坐标以根窗口左上角为原点，与 Windows 一样只需要一个蒙层窗口
+pub struct X11Backend;
+
+impl CaptureBackend for X11Backend {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            virtual_desktop: true,
+            windows: true,
+        }
+    }
+
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>, CaptureError> {
+        list_monitors()
+    }
+
+    fn capture_monitor(&self, monitor_id: u32) -> Result<Frame, CaptureError> {
+        let monitor = list_monitors()?
+            .into_iter()
+            .find(|monitor| monitor.id == monitor_id)
+            .ok_or_else(|| {
+                log::error!("Failed to find monitor with id: {}", monitor_id);
+                CaptureError::FailedToFindDisplay
+            })?;
+        self.capture_region(&monitor.bounds)
+    }
+
+    fn capture_region(&self, region: &Rect) -> Result<Frame, CaptureError> {
+        let (width, height) = screen_size()?;
+        let Some((x, y, width, height)) = clamp_region(region, width, height) else {
+            log::error!("Region {:?} is out of root window", region);
+            return Err(CaptureError::RegionOutOfBounds);
+        };
+        capture_area(x, y, width, height)
+    }
+
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>, CaptureError> {
+        enumerate::x11::enumerate_windows().ok_or(CaptureError::FailedToEnumerateWindows)
+    }
+
+    fn capture_desktop(&self) -> Result<Frame, CaptureError> {
+        capscreen()
+    }
+}
+
+/// 截取整个 X 虚拟桌面（根窗口），优先使用 MIT-SHM，不可用时回退到 XGetImage
+pub fn capscreen() -> Result<Frame, CaptureError> {
+    let (width, height) = screen_size()?;
+    capture_area(0, 0, width, height)
+}
+
+/// 将区域裁剪到根窗口内并转换为 X 协议的坐标，与根窗口没有交集时返回 None
+/// X 协议的坐标为 i16，起点超过 i16::MAX 的区域无法截取
+fn clamp_region(region: &Rect, width: u16, height: u16) -> Option<(i16, i16, u16, u16)> {
+    let values = [region.x, region.y, region.width, region.height];
+    if values.iter().any(|value| !value.is_finite()) || region.width <= 0.0 || region.height <= 0.0
+    {
+        return None;
+    }
+    let (width, height) = (width as f64, height as f64);
+    let left = region.x.floor().clamp(0.0, width);
+    let top = region.y.floor().clamp(0.0, height);
+    let right = (region.x + region.width).ceil().clamp(0.0, width);
+    let bottom = (region.y + region.height).ceil().clamp(0.0, height);
+    if right <= left || bottom <= top || left > i16::MAX as f64 || top > i16::MAX as f64 {
+        return None;
+    }
+    Some((
+        left as i16,
+        top as i16,
+        (right - left) as u16,
+        (bottom - top) as u16,
+    ))
+}
+
+/// 截取根窗口坐标下的区域
+fn capture_area(x: i16, y: i16, width: u16, height: u16) -> Result<Frame, CaptureError> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| {
+        log::error!("Failed to connect to X server: {:?}", e);
+        CaptureError::FailedToConnectDisplay
+    })?;
+    let screen = &conn.setup().roots[screen_num];
+    check_pixel_format(&conn, screen)?;
+
+    let mut data = match capture_shm(&conn, screen.root, x, y, width, height) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("MIT-SHM capture unavailable, fallback to GetImage: {:?}", e);
+            capture_get_image(&conn, screen.root, x, y, width, height)?
+        }
+    };
+
+    // ZPixmap 32位小端为 BGRX，转换为 RGBA
+    data.par_chunks_exact_mut(4).for_each(|pixel| {
+        pixel.swap(0, 2);
+        pixel[3] = 255;
+    });
+
+    Ok(Frame {
+        data,
+        width: width as u32,
+        height: height as u32,
+    })
+}
+
+/// X 根窗口的尺寸即虚拟桌面的尺寸，原点始终为(0, 0)
+pub fn screen_size() -> Result<(u16, u16), CaptureError> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| {
+        log::error!("Failed to connect to X server: {:?}", e);
+        CaptureError::FailedToConnectDisplay
+    })?;
+    let screen = &conn.setup().roots[screen_num];
+    Ok((screen.width_in_pixels, screen.height_in_pixels))
+}
+
+/// 通过 RandR 获取显示器布局，X11 没有缩放的概念，scale_factor 固定为 1
+pub fn list_monitors() -> Result<Vec<MonitorInfo>, CaptureError> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| {
+        log::error!("Failed to connect to X server: {:?}", e);
+        CaptureError::FailedToConnectDisplay
+    })?;
+    let root = conn.setup().roots[screen_num].root;
+    let reply = conn
+        .randr_get_monitors(root, true)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .ok_or_else(|| {
+            log::error!("RRGetMonitors failed");
+            CaptureError::FailedToFindDisplay
+        })?;
+    Ok(reply
+        .monitors
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| {
+            let name = conn
+                .get_atom_name(monitor.name)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+                .unwrap_or_default();
+            MonitorInfo {
+                id: index as u32,
+                name,
+                bounds: Rect {
+                    x: monitor.x as f64,
+                    y: monitor.y as f64,
+                    width: monitor.width as f64,
+                    height: monitor.height as f64,
+                },
+                scale_factor: 1.0,
+                is_primary: monitor.primary,
+            }
+        })
+        .collect())
+}
+
+/// 设置 _NET_WM_STATE_ABOVE 和 _NET_WM_STATE_FULLSCREEN，
+/// 并通过 _NET_WM_FULLSCREEN_MONITORS 让全屏覆盖所有显示器
+pub fn configure_overlay_window(window: &Window) {
+    let gtk_window = window.gtk_window();
+    gtk_window.set_keep_above(true);
+    if let Some(gdk_window) = gtk_window.window() {
+        gdk_window.set_fullscreen_mode(gtk::gdk::FullscreenMode::AllMonitors);
+    }
+    gtk_window.fullscreen();
+    log::error!("Configured window as overlay with _NET_WM_STATE_ABOVE");
+}
+
+/// 只支持常见的 24/32 位深 BGRX 布局，其他布局（如 16 位色）直接报错
+fn check_pixel_format(conn: &RustConnection, screen: &Screen) -> Result<(), CaptureError> {
+    let setup = conn.setup();
+    let bits_per_pixel = setup
+        .pixmap_formats
+        .iter()
+        .find(|format| format.depth == screen.root_depth)
+        .map(|format| format.bits_per_pixel);
+    let visual = screen
+        .allowed_depths
+        .iter()
+        .flat_map(|depth| depth.visuals.iter())
+        .find(|visual| visual.visual_id == screen.root_visual);
+
+    let supported = bits_per_pixel == Some(32)
+        && setup.image_byte_order == ImageOrder::LSB_FIRST
+        && visual.is_some_and(|visual| {
+            visual.red_mask == 0xff0000 && visual.green_mask == 0xff00 && visual.blue_mask == 0xff
+        });
+    if !supported {
+        log::error!(
+            "Unsupported pixel format, depth: {}, bits_per_pixel: {:?}",
+            screen.root_depth,
+            bits_per_pixel
+        );
+        return Err(CaptureError::UnsupportedPixelFormat);
+    }
+    Ok(())
+}
+
+fn capture_shm(
+    conn: &RustConnection,
+    root: u32,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+) -> Result<Vec<u8>, CaptureError> {
+    conn.shm_query_version()
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .ok_or(CaptureError::FailedToGetBuffer)?;
+
+    let size = width as usize * height as usize * 4;
+    let segment = ShmSegment::new(size)?;
+    let seg = conn.generate_id().map_err(|_| CaptureError::FailedToGetBuffer)?;
+    conn.shm_attach(seg, segment.id as u32, false)
+        .ok()
+        .and_then(|cookie| cookie.check().ok())
+        .ok_or(CaptureError::FailedToGetBuffer)?;
+
+    let result = conn
+        .shm_get_image(
+            root,
+            x,
+            y,
+            width,
+            height,
+            !0,
+            ImageFormat::Z_PIXMAP.into(),
+            seg,
+            0,
+        )
+        .ok()
+        .and_then(|cookie| cookie.reply().ok());
+    _ = conn.shm_detach(seg).map(|cookie| cookie.check());
+
+    if result.is_none() {
+        log::error!("ShmGetImage failed");
+        return Err(CaptureError::FailedToCaptureImage);
+    }
+    Ok(segment.as_slice().to_vec())
+}
+
+fn capture_get_image(
+    conn: &RustConnection,
+    root: u32,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+) -> Result<Vec<u8>, CaptureError> {
+    let reply = conn
+        .get_image(ImageFormat::Z_PIXMAP, root, x, y, width, height, !0)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .ok_or_else(|| {
+            log::error!("GetImage failed");
+            CaptureError::FailedToCaptureImage
+        })?;
+    if reply.data.len() < width as usize * height as usize * 4 {
+        log::error!("GetImage returned {} bytes", reply.data.len());
+        return Err(CaptureError::FailedToGetBuffer);
+    }
+    Ok(reply.data)
+}
+
+/// SysV 共享内存段，Drop 时自动 detach 并标记删除
+struct ShmSegment {
+    id: i32,
+    addr: *mut libc::c_void,
+    size: usize,
+}
+
+impl ShmSegment {
+    fn new(size: usize) -> Result<Self, CaptureError> {
+        unsafe {
+            let id = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+            if id == -1 {
+                log::error!("shmget failed");
+                return Err(CaptureError::FailedToGetBuffer);
+            }
+            let addr = libc::shmat(id, std::ptr::null(), 0);
+            if addr as isize == -1 {
+                log::error!("shmat failed");
+                libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut());
+                return Err(CaptureError::FailedToGetBuffer);
+            }
+            Ok(Self { id, addr, size })
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.addr as *const u8, self.size) }
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::shmdt(self.addr);
+            libc::shmctl(self.id, libc::IPC_RMID, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 需要 X 显示，默认跳过，可以在 Xvfb 中运行: `Xvfb :99 & DISPLAY=:99 cargo test x11 -- --ignored`
+    #[test]
+    #[ignore = "requires an X display"]
+    fn test_capscreen() {
+        let frame = capscreen().expect("capscreen failed");
+        println!("frame: {}x{}", frame.width, frame.height);
+        assert_eq!(frame.data.len(), (frame.width * frame.height * 4) as usize);
+    }
+
+    #[test]
+    fn test_clamp_region() {
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            clamp_region(&rect(10.5, 20.0, 100.0, 50.0), 1920, 1080),
+            Some((10, 20, 101, 50))
+        );
+        assert_eq!(
+            clamp_region(&rect(-100.0, 1000.0, 300.0, 500.0), 1920, 1080),
+            Some((0, 1000, 200, 80))
+        );
+        assert_eq!(
+            clamp_region(&rect(40000.0, 0.0, 100.0, 100.0), 60000, 1080),
+            None
+        );
+        assert_eq!(
+            clamp_region(&rect(2000.0, 0.0, 10.0, 10.0), 1920, 1080),
+            None
+        );
+        assert_eq!(clamp_region(&rect(0.0, 0.0, 0.0, 10.0), 1920, 1080), None);
+        assert_eq!(
+            clamp_region(&rect(f64::NAN, 0.0, 10.0, 10.0), 1920, 1080),
+            None
+        );
+    }
+}
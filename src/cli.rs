@@ -0,0 +1,325 @@
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    AppConfig, AppConfigBuilder, CaptureMode, ConfigError, ConfigFile, ImageOutput, RpcTransport,
+    SaveHandler,
+    capscreen::{encode::ImageFormat, enumerate::Rect},
+    exit_code::EXIT_USAGE,
+    stdio::Framing,
+};
+
+/// QuickCap is a tool for capturing screenshots of your screen.
+#[derive(Debug, Parser)]
+#[command(name = "quickcap", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub options: Options,
+}
+
+/// 所有子命令共用的参数，对应 AppConfig 的字段，优先于配置文件
+#[derive(Debug, Args)]
+pub struct Options {
+    /// Configuration file [default: <config dir>/quickcap/config.toml]
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Ignore the configuration file
+    #[arg(long, conflicts_with = "config", global = true)]
+    pub no_config: bool,
+
+    /// Do not keep the overlay topmost, so other windows can be focused
+    #[arg(long, global = true)]
+    pub debug: bool,
+
+    /// Image format used when saving or writing a capture
+    #[arg(long, value_enum, global = true)]
+    pub format: Option<ImageFormat>,
+
+    /// Default directory of the save dialog [default: downloads]
+    #[arg(long, value_name = "DIR", global = true)]
+    pub save_dir: Option<PathBuf>,
+
+    /// Initial mode of the overlay
+    #[arg(long, value_enum, global = true)]
+    pub mode: Option<CaptureMode>,
+
+    /// Who picks the save location: the native dialog, the host's `chooseSavePath` method,
+    /// or no file at all and the image is sent to the host in a `capture_data` notification
+    #[arg(long, value_enum, value_name = "HANDLER", global = true)]
+    pub save_handler: Option<SaveHandler>,
+
+    /// Put the final image in the `capture_result` notification, as base64 or as a temporary
+    /// file; confirming the selection then only delivers the image and skips `post_capture`
+    #[arg(long, value_enum, value_name = "OUTPUT", global = true)]
+    pub image_output: Option<ImageOutput>,
+
+    /// Seconds to wait before capturing
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, global = true)]
+    pub delay: Option<Duration>,
+
+    /// JSON-RPC transport to the host: `stdio`, `unix:PATH`, `tcp:PORT` or `pipe:NAME`
+    #[arg(long, value_name = "TRANSPORT", global = true)]
+    pub rpc: Option<RpcTransport>,
+
+    /// Framing of outgoing JSON-RPC messages, incoming messages may use either
+    #[arg(long, value_enum, value_name = "FRAMING", global = true)]
+    pub rpc_framing: Option<Framing>,
+
+    /// Inherited file descriptor (a handle on Windows) to use as the JSON-RPC channel
+    #[arg(long, value_name = "FD", conflicts_with = "rpc", global = true)]
+    pub rpc_fd: Option<i32>,
+
+    /// Keep the overlay hidden until the host sends the JSON-RPC `initialize` request
+    #[arg(long, global = true)]
+    pub wait_initialize: bool,
+
+    /// Exit when this process exits [default: the parent process, on Unix]
+    #[arg(long, value_name = "PID", global = true)]
+    pub parent_pid: Option<u32>,
+
+    /// Exit after this many seconds without input or JSON-RPC messages, 0 to disable
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, global = true)]
+    pub idle_timeout: Option<Duration>,
+
+    /// Load the background from a PNG instead of capturing the screen
+    #[arg(long, value_name = "PNG", global = true)]
+    pub capture_from: Option<PathBuf>,
+
+    /// Load windows from a JSON array of {name, bounds}, requires --capture-from
+    #[arg(long, value_name = "JSON", requires = "capture_from", global = true)]
+    pub windows_from: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Capture without the overlay and write the image to a file or stdout
+    Capture(CaptureArgs),
+    /// Print the windows seen by the capture backend as JSON
+    ListWindows,
+    /// Print the monitors seen by the capture backend as JSON
+    ListMonitors,
+    /// Print the OpenRPC description of the JSON-RPC methods
+    RpcSchema,
+}
+
+#[derive(Debug, Args)]
+pub struct CaptureArgs {
+    /// Monitor id from list-monitors, or `all` for the whole desktop
+    #[arg(long, value_name = "ID|all", default_value = "all")]
+    pub monitor: MonitorArg,
+
+    /// Region `x,y,w,h`, relative to the monitor when --monitor is an id
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_region)]
+    pub region: Option<Rect>,
+
+    /// Output path, `-` for stdout
+    #[arg(long, value_name = "PATH", default_value = "-")]
+    pub output: String,
+}
+
+/// 要截取的显示器，`all` 为整个虚拟桌面
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorArg {
+    All,
+    Id(u32),
+}
+
+impl FromStr for MonitorArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(MonitorArg::All),
+            id => id
+                .parse()
+                .map(MonitorArg::Id)
+                .map_err(|_| format!("expected a monitor id or `all`, got `{}`", id)),
+        }
+    }
+}
+
+impl Options {
+    /// 先应用配置文件，再用命令行参数覆盖
+    pub fn to_config(&self) -> Result<AppConfig, Vec<ConfigError>> {
+        let mut builder = AppConfigBuilder::new();
+        if let Some(file) = self.load_config_file()? {
+            builder = builder.with_config_file(file);
+        }
+        builder = builder.with_debug(self.debug);
+        if let Some(format) = self.format {
+            builder = builder.with_format(format);
+        }
+        if let Some(save_dir) = &self.save_dir {
+            builder = builder.with_save_dir(save_dir);
+        }
+        if let Some(mode) = self.mode {
+            builder = builder.with_mode(mode);
+        }
+        if let Some(handler) = self.save_handler {
+            builder = builder.with_save_handler(handler);
+        }
+        if let Some(output) = self.image_output {
+            builder = builder.with_image_output(output);
+        }
+        if let Some(delay) = self.delay {
+            builder = builder.with_delay(delay);
+        }
+        if let Some(rpc) = &self.rpc {
+            builder = builder.with_rpc_transport(rpc.clone());
+        }
+        if let Some(fd) = self.rpc_fd {
+            builder = builder.with_rpc_transport(RpcTransport::Fd(fd));
+        }
+        if let Some(framing) = self.rpc_framing {
+            builder = builder.with_rpc_framing(framing);
+        }
+        builder = builder.with_wait_initialize(self.wait_initialize);
+        if let Some(pid) = self.parent_pid {
+            builder = builder.with_parent_pid(pid);
+        }
+        if let Some(timeout) = self.idle_timeout {
+            builder = builder.with_idle_timeout(timeout);
+        }
+        if let Some(capture_from) = &self.capture_from {
+            builder = builder.with_capture_from(capture_from);
+        }
+        if let Some(windows_from) = &self.windows_from {
+            builder = builder.with_windows_from(windows_from);
+        }
+        Ok(builder.build())
+    }
+
+    /// 与 clap 的参数错误一样，打印所有错误后以退出码 2 退出进程
+    pub fn to_config_or_exit(&self) -> AppConfig {
+        self.to_config().unwrap_or_else(|errors| {
+            for error in errors {
+                eprintln!("error: {}", error);
+            }
+            std::process::exit(EXIT_USAGE);
+        })
+    }
+
+    /// 显式指定的文件必须存在，默认位置的文件不存在时忽略
+    fn load_config_file(&self) -> Result<Option<ConfigFile>, Vec<ConfigError>> {
+        if self.no_config {
+            return Ok(None);
+        }
+        match &self.config {
+            Some(path) if !path.exists() => Err(vec![ConfigError {
+                path: path.clone(),
+                message: "no such file".to_string(),
+            }]),
+            Some(path) => ConfigFile::load(path).map(Some),
+            None => match ConfigFile::default_path() {
+                Some(path) => ConfigFile::load(&path).map(Some),
+                None => Ok(None),
+            },
+        }
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("expected a non-negative number of seconds, got `{}`", value))
+}
+
+/// 解析 `x,y,w,h`
+fn parse_region(value: &str) -> Result<Rect, String> {
+    let parts = value
+        .split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("expected `x,y,w,h`, got `{}`", value))?;
+    match parts[..] {
+        [x, y, width, height] if width > 0.0 && height > 0.0 => Ok(Rect {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => Err(format!(
+            "expected `x,y,w,h` with positive width and height, got `{}`",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cli() {
+        let cli = Cli::try_parse_from([
+            "quickcap",
+            "--no-config",
+            "--format",
+            "jpeg",
+            "--mode",
+            "window",
+            "--delay",
+            "1.5",
+            "--idle-timeout",
+            "30",
+            "--save-handler",
+            "host",
+            "--image-output",
+            "inline",
+            "capture",
+            "--monitor",
+            "1",
+            "--region",
+            "10,20,300,200",
+        ])
+        .unwrap();
+        let config = cli.options.to_config().unwrap();
+        assert_eq!(config.format(), ImageFormat::Jpeg);
+        assert_eq!(config.mode(), CaptureMode::Window);
+        assert_eq!(config.delay(), Duration::from_millis(1500));
+        assert_eq!(config.idle_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(config.parent_pid(), None);
+        assert_eq!(config.save_handler(), SaveHandler::Host);
+        assert_eq!(config.image_output(), ImageOutput::Inline);
+        let Some(Command::Capture(args)) = cli.command else {
+            panic!("expected capture command");
+        };
+        assert_eq!(args.monitor, MonitorArg::Id(1));
+        assert_eq!(args.output, "-");
+        let region = args.region.unwrap();
+        assert_eq!(
+            (region.x, region.y, region.width, region.height),
+            (10.0, 20.0, 300.0, 200.0)
+        );
+
+        let cli = Cli::try_parse_from(["quickcap", "--no-config", "--format", "webp"]).unwrap();
+        assert_eq!(cli.options.to_config().unwrap().format(), ImageFormat::Webp);
+
+        assert!(Cli::try_parse_from(["quickcap", "--verbose"]).is_err());
+        assert!(Cli::try_parse_from(["quickcap", "--delay", "-1"]).is_err());
+        assert!(Cli::try_parse_from(["quickcap", "--rpc", "http://x"]).is_err());
+        assert!(Cli::try_parse_from(["quickcap", "--rpc", "tcp:10.0.0.1:80"]).is_err());
+        assert!(Cli::try_parse_from(["quickcap", "--rpc", "stdio", "--rpc-fd", "3"]).is_err());
+        assert_eq!(
+            "tcp:4000".parse::<RpcTransport>(),
+            Ok(RpcTransport::Tcp(([127, 0, 0, 1], 4000).into()))
+        );
+        assert_eq!(
+            "unix:/tmp/quickcap.sock".parse::<RpcTransport>(),
+            Ok(RpcTransport::Unix("/tmp/quickcap.sock".into()))
+        );
+        assert!(Cli::try_parse_from(["quickcap", "capture", "--region", "1,2,3"]).is_err());
+        assert!(Cli::try_parse_from(["quickcap", "--windows-from", "w.json"]).is_err());
+
+        let cli =
+            Cli::try_parse_from(["quickcap", "--config", "/nonexistent/config.toml"]).unwrap();
+        assert!(cli.options.to_config().is_err());
+    }
+}
@@ -0,0 +1,10 @@
+//! 进程退出码，无界面子命令、参数校验和蒙层退出共用
+
+/// 成功
+pub const EXIT_SUCCESS: i32 = 0;
+/// 截图或写入失败
+pub const EXIT_FAILURE: i32 = 1;
+/// 参数错误，与 clap 的退出码一致
+pub const EXIT_USAGE: i32 = 2;
+/// 蒙层关闭时没有保存或复制
+pub const EXIT_CANCELLED: i32 = 3;
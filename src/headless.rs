@@ -0,0 +1,121 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use serde::Serialize;
+
+use crate::{
+    AppConfig,
+    app::commands,
+    capscreen::{
+        CaptureBackend, CaptureError, Frame,
+        encode::{EncodeOptions, encode_frame},
+        enumerate::Rect,
+    },
+    cli::{CaptureArgs, MonitorArg},
+    exit_code::{EXIT_FAILURE, EXIT_SUCCESS},
+};
+
+/// 无界面截图，直接调用截图后端并编码，不创建事件循环和 webview
+/// 返回进程退出码
+pub fn capture(args: &CaptureArgs, config: &AppConfig) -> i32 {
+    std::thread::sleep(config.delay());
+    let result = config
+        .capture_backend()
+        .and_then(|backend| capture_frame(backend.as_ref(), args));
+    let frame = match result {
+        Ok(frame) => frame,
+        Err(e) => {
+            eprintln!("capture failed: {:?}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    match write_frame(&frame, &args.output, &config.encode_options()) {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("write {} failed: {:?}", args.output, e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// 以 JSON 输出后端枚举到的窗口，格式与 /windows 相同
+pub fn list_windows(config: &AppConfig) -> i32 {
+    print_json(
+        config
+            .capture_backend()
+            .and_then(|backend| backend.enumerate_windows()),
+    )
+}
+
+/// 以 JSON 输出后端的显示器列表
+pub fn list_monitors(config: &AppConfig) -> i32 {
+    print_json(
+        config
+            .capture_backend()
+            .and_then(|backend| backend.list_monitors()),
+    )
+}
+
+/// 输出 JSON-RPC 方法的 OpenRPC 文档，与 rpc.discover 的结果相同，供宿主生成代码
+pub fn rpc_schema() -> i32 {
+    print_json(Ok(commands::registry(|_| {}).discover()))
+}
+
+fn print_json<T: Serialize>(result: Result<T, CaptureError>) -> i32 {
+    let value = match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("enumerate failed: {:?}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    match serde_json::to_string_pretty(&value) {
+        Ok(json) => {
+            println!("{}", json);
+            EXIT_SUCCESS
+        }
+        Err(e) => {
+            eprintln!("serialize failed: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn capture_frame(backend: &dyn CaptureBackend, args: &CaptureArgs) -> Result<Frame, CaptureError> {
+    match (&args.monitor, &args.region) {
+        (MonitorArg::All, None) => backend.capture_desktop(),
+        (MonitorArg::All, Some(region)) => backend.capture_region(region),
+        (MonitorArg::Id(id), None) => backend.capture_monitor(*id),
+        (MonitorArg::Id(id), Some(region)) => {
+            let monitor = backend
+                .list_monitors()?
+                .into_iter()
+                .find(|monitor| monitor.id == *id)
+                .ok_or(CaptureError::FailedToFindDisplay)?;
+            backend.capture_region(&Rect {
+                x: monitor.bounds.x + region.x,
+                y: monitor.bounds.y + region.y,
+                width: region.width,
+                height: region.height,
+            })
+        }
+    }
+}
+
+fn write_frame(frame: &Frame, output: &str, options: &EncodeOptions) -> Result<(), CaptureError> {
+    if output == "-" {
+        let mut stdout = std::io::stdout().lock();
+        encode_frame(BufWriter::new(&mut stdout), frame, options)?;
+        return stdout.flush().map_err(|e| {
+            log::error!("Failed to flush stdout: {}", e);
+            CaptureError::FailedToEncodeImage
+        });
+    }
+    let file = File::create(output).map_err(|e| {
+        log::error!("Failed to create {}: {}", output, e);
+        CaptureError::FailedToEncodeImage
+    })?;
+    encode_frame(BufWriter::new(file), frame, options)
+}
@@ -1,9 +1,21 @@
 mod app;
 
 pub mod capscreen;
+pub mod cli;
+pub mod exit_code;
+pub mod headless;
 pub mod stdio;
 
 pub use app::App;
+pub use app::AppError;
 pub use app::AppConfig;
 pub use app::AppConfigBuilder;
+pub use app::CaptureMode;
+pub use app::ConfigError;
+pub use app::ConfigFile;
+pub use app::ImageOutput;
+pub use app::PostCaptureAction;
+pub use app::RpcTransport;
+pub use app::SaveHandler;
+pub use app::Theme;
 pub use stdio::StdRpcClient;
@@ -1,6 +1,25 @@
-use quickcap::{App, AppConfig};
+use clap::Parser;
+use quickcap::{
+    App, headless,
+    cli::{Cli, Command},
+};
 
 fn main() {
-    let app = App::new(Some(AppConfig::from_args()));
-    app.run();
+    let cli = Cli::parse();
+    let config = cli.options.to_config_or_exit();
+    // 无界面的子命令，不启动事件循环和 webview
+    let code = match &cli.command {
+        Some(Command::Capture(args)) => headless::capture(args, &config),
+        Some(Command::ListWindows) => headless::list_windows(&config),
+        Some(Command::ListMonitors) => headless::list_monitors(&config),
+        Some(Command::RpcSchema) => headless::rpc_schema(),
+        None => match App::new(Some(config)) {
+            Ok(app) => app.run(),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                e.exit_code()
+            }
+        },
+    };
+    std::process::exit(code);
 }
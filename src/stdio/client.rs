@@ -1,13 +1,17 @@
-use std::collections::HashMap;
-use std::io::{self, BufRead, Write};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{mpsc, Mutex, OnceLock};
-use std::thread;
-use std::time::Duration;
+use std::io::BufRead;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::stdio::{
+    peer::{Outgoing, RpcPeer},
+    transport::Connection,
+};
+
 
 #[derive(Debug, Clone)]
 pub struct RpcRequest {
@@ -16,6 +20,99 @@ pub struct RpcRequest {
     pub id: Value, // ID 可以是 Number 或 String
 }
 
+/// 进行中的宿主请求，RpcResponder 的回复和宿主的 `$/cancelRequest` 共用，保证只回复一次
+#[derive(Debug)]
+pub(crate) struct InflightRequest {
+    id: Value,
+    sink: ReplySink,
+    done: AtomicBool,
+}
+
+impl InflightRequest {
+    pub(crate) fn new(id: Value, sink: ReplySink) -> Self {
+        Self {
+            id,
+            sink,
+            done: AtomicBool::new(false),
+        }
+    }
+
+    /// 进行中的请求以 id 的 JSON 文本为键，字符串 "1" 和数字 1 是不同的请求
+    pub(crate) fn key(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    /// 只有第一次调用会写出回复
+    pub(crate) fn reply(&self, out: &dyn Outgoing, result: Result<Value, RpcError>) {
+        if self.done.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let (res, err) = match result {
+            Ok(v) => (Some(v), None),
+            Err(e) => (None, Some(e)),
+        };
+
+        let resp = RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: res,
+            error: err,
+            id: self.id.clone(),
+        };
+
+        match &self.sink {
+            ReplySink::Single => out.write_raw(serde_json::to_value(&resp).unwrap()),
+            ReplySink::Batch(batch) => batch.push(resp),
+        }
+    }
+}
+
+/// 请求的回复句柄，可以移动到事件循环或工作线程中，处理完成后调用 `respond` 回复
+/// 未回复就被丢弃时自动回复内部错误，保证每个请求有且只有一个回复
+#[derive(Debug)]
+pub struct RpcResponder {
+    request: Arc<InflightRequest>,
+    out: Arc<dyn Outgoing>,
+}
+
+impl RpcResponder {
+    pub(crate) fn new(request: Arc<InflightRequest>, out: Arc<dyn Outgoing>) -> Self {
+        Self { request, out }
+    }
+
+    pub fn id(&self) -> &Value {
+        &self.request.id
+    }
+
+    /// 宿主已通过 `$/cancelRequest` 取消并收到了 -32800，之后的回复会被忽略，耗时的处理可以提前结束
+    pub fn is_cancelled(&self) -> bool {
+        self.request.is_done()
+    }
+
+    pub fn respond(self, result: Result<Value, RpcError>) {
+        self.finish(result);
+    }
+
+    fn finish(&self, result: Result<Value, RpcError>) {
+        self.request.reply(&*self.out, result);
+        self.out.finish(&self.request);
+    }
+}
+
+impl Drop for RpcResponder {
+    fn drop(&mut self) {
+        if !self.request.is_done() {
+            log::error!("Request {} dropped without response", self.request.id);
+            self.finish(Err(RpcError::internal_error(
+                "request dropped without response",
+            )));
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RpcNotification {
     pub method: String,
@@ -32,226 +129,261 @@ pub struct RpcResponse {
     pub id: Value,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RpcError {
     pub code: i32,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub data: Option<Value>,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct RawMessage {
-    jsonrpc: Option<String>,
-    method: Option<String>,
-    params: Option<Value>,
-    result: Option<Value>,
-    error: Option<RpcError>,
-    id: Option<Value>,
-}
+impl RpcError {
+    pub const PARSE_ERROR: i32 = -32700;
+    pub const INVALID_REQUEST: i32 = -32600;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INVALID_PARAMS: i32 = -32602;
+    pub const INTERNAL_ERROR: i32 = -32603;
+    /// 与 LSP 相同，请求被 `$/cancelRequest` 取消
+    pub const REQUEST_CANCELLED: i32 = -32800;
+
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
 
+    pub fn parse_error(message: impl Into<String>) -> Self {
+        Self::new(Self::PARSE_ERROR, message)
+    }
 
-static INSTANCE: OnceLock<StdRpcClient> = OnceLock::new();
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_REQUEST, message)
+    }
 
-pub struct StdRpcClient {
-    next_id: AtomicU64,
-    // 存储发出的请求，等待回复: Map<ID, Sender>
-    pending_requests: Mutex<HashMap<String, mpsc::Sender<RpcResponse>>>,
-}
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(Self::METHOD_NOT_FOUND, format!("method not found: {}", method))
+    }
 
-impl StdRpcClient {
-    pub fn global() -> &'static Self {
-        INSTANCE.get().expect("StdRpcClient not initialized! Call init() first.")
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_PARAMS, message)
     }
 
-    pub fn send_notification(&self, method: &str, params: Option<Value>) {
-        // 构建原始 JSON 对象发送，避免定义冗余的 OutgoingNotification 结构
-        let msg = serde_json::json!({
-            "jsonrpc": "2.0",
-            "method": method,
-            "params": params
-        });
-        self.write_raw(msg);
+    pub fn internal_error(message: impl Into<String>) -> Self {
+        Self::new(Self::INTERNAL_ERROR, message)
     }
 
-    pub fn call(
-        &self,
-        method: &str,
-        params: Option<Value>,
-        timeout: Duration,
-    ) -> Result<Value, String> {
-        let id_num = self.next_id.fetch_add(1, Ordering::Relaxed);
-        let id_str = id_num.to_string(); // 统一转为 String 处理 ID 比较方便
-
-        let (tx, rx) = mpsc::channel();
-
-        // 注册 Pending
-        {
-            let mut map = self.pending_requests.lock().unwrap();
-            map.insert(id_str.clone(), tx);
-        }
+    pub fn request_cancelled() -> Self {
+        Self::new(Self::REQUEST_CANCELLED, "request cancelled")
+    }
+}
+
+/// 校验后的单条消息
+#[derive(Debug)]
+pub(crate) enum Message {
+    Request(RpcRequest),
+    Notification(RpcNotification),
+    Response(RpcResponse),
+}
 
-        // 发送数据
-        let msg = serde_json::json!({
-            "jsonrpc": "2.0",
-            "method": method,
-            "params": params,
-            "id": id_num 
-        });
-        self.write_raw(msg);
-
-        // 等待响应
-        let result = rx.recv_timeout(timeout);
-
-        // 清理 Map
-        {
-            let mut map = self.pending_requests.lock().unwrap();
-            map.remove(&id_str);
+/// 回复的去向，批量请求的回复全部收集后作为一个数组写出
+#[derive(Debug, Clone)]
+pub(crate) enum ReplySink {
+    Single,
+    Batch(Arc<Batch>),
+}
+
+#[derive(Debug)]
+pub(crate) struct Batch {
+    /// 已收集的回复，以及还没有回复的数量
+    state: Mutex<(Vec<RpcResponse>, usize)>,
+    out: Arc<dyn Outgoing>,
+}
+
+impl Batch {
+    pub(crate) fn new(expected: usize, out: Arc<dyn Outgoing>) -> Self {
+        Self {
+            state: Mutex::new((Vec::with_capacity(expected), expected)),
+            out,
         }
+    }
 
-        match result {
-            Ok(resp) => {
-                if let Some(err) = resp.error {
-                    Err(format!("RPC Error {}: {}", err.code, err.message))
-                } else if let Some(res) = resp.result {
-                    Ok(res)
-                } else {
-                    Ok(Value::Null) // 成功但结果为 null
-                }
-            }
-            Err(mpsc::RecvTimeoutError::Timeout) => Err("Request timed out".to_string()),
-            Err(mpsc::RecvTimeoutError::Disconnected) => Err("Channel disconnected".to_string()),
+    fn push(&self, response: RpcResponse) {
+        let mut state = self.state.lock().unwrap();
+        state.0.push(response);
+        state.1 -= 1;
+        if state.1 == 0 {
+            let responses = std::mem::take(&mut state.0);
+            drop(state);
+            self.out.write_raw(serde_json::to_value(responses).unwrap());
         }
     }
+}
 
-    /// 内部：写 JSON 到 stdout
-    fn write_raw(&self, msg: Value) {
-        let mut json = serde_json::to_string(&msg).unwrap();
-        json.push('\n'); // 必须有换行符
-        
-        let stdout = io::stdout();
-        let mut handle = stdout.lock();
-        if let Err(e) = handle.write_all(json.as_bytes()) {
-            log::error!("Failed to write to stdout: {}", e);
+/// 按 JSON-RPC 2.0 校验单条消息，不合法时返回需要回复的 id(无法确定时为 null)和错误
+pub(crate) fn parse_message(value: Value) -> Result<Message, (Value, RpcError)> {
+    let Value::Object(mut obj) = value else {
+        return Err((Value::Null, RpcError::invalid_request("message must be an object")));
+    };
+    // id 只能是字符串、数字或 null，否则无法确定回复哪个请求
+    let id = match obj.remove("id") {
+        None => None,
+        Some(id @ (Value::String(_) | Value::Number(_) | Value::Null)) => Some(id),
+        Some(_) => {
+            return Err((
+                Value::Null,
+                RpcError::invalid_request("id must be a string, number or null"),
+            ))
         }
-        let _ = handle.flush();
+    };
+    let reply_id = id.clone().unwrap_or(Value::Null);
+    if obj.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err((reply_id, RpcError::invalid_request("jsonrpc must be \"2.0\"")));
     }
+    match obj.remove("method") {
+        Some(Value::String(method)) => {
+            let params = match obj.remove("params") {
+                None => None,
+                Some(params @ (Value::Object(_) | Value::Array(_))) => Some(params),
+                Some(_) => {
+                    return Err((
+                        reply_id,
+                        RpcError::invalid_request("params must be an object or array"),
+                    ))
+                }
+            };
+            Ok(match id {
+                Some(id) => Message::Request(RpcRequest { method, params, id }),
+                None => Message::Notification(RpcNotification { method, params }),
+            })
+        }
+        Some(_) => Err((reply_id, RpcError::invalid_request("method must be a string"))),
+        // 无 Method 有 ID: 是我们主动发起的请求的响应
+        None => match id {
+            Some(id) => {
+                let error = obj.remove("error").map(|error| {
+                    serde_json::from_value::<RpcError>(error).unwrap_or_else(|e| {
+                        RpcError::internal_error(format!("invalid error object: {}", e))
+                    })
+                });
+                Ok(Message::Response(RpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    result: obj.remove("result"),
+                    error,
+                    id,
+                }))
+            }
+            None => Err((Value::Null, RpcError::invalid_request("missing method"))),
+        },
+    }
+}
 
-    /// 内部：自动回复响应 (Rust -> Electron)
-    fn send_response(&self, id: Value, result: Result<Value, RpcError>) {
-        let (res, err) = match result {
-            Ok(v) => (Some(v), None),
-            Err(e) => (None, Some(e)),
-        };
 
-        let resp = RpcResponse {
-            jsonrpc: "2.0".to_string(),
-            result: res,
-            error: err,
-            id,
-        };
-        
-        // 序列化 RpcResponse
-        let mut json = serde_json::to_string(&resp).unwrap();
-        json.push('\n');
-
-        let stdout = io::stdout();
-        let mut handle = stdout.lock();
-        let _ = handle.write_all(json.as_bytes());
-        let _ = handle.flush();
+static INSTANCE: OnceLock<StdRpcClient> = OnceLock::new();
+
+/// 进程中与宿主通信的全局 RpcPeer，通道由 --rpc 选择
+pub struct StdRpcClient {
+    peer: RpcPeer<Box<dyn BufRead + Send>>,
+}
+
+impl Deref for StdRpcClient {
+    type Target = RpcPeer<Box<dyn BufRead + Send>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.peer
+    }
+}
+
+impl StdRpcClient {
+    pub fn global() -> &'static Self {
+        INSTANCE.get().expect("StdRpcClient not initialized! Call init() first.")
     }
 
     // --- 初始化函数 ---
 
-    /// 初始化 RPC 系统
-    /// 
-    /// # 参数
-    /// * `on_request`: 处理请求的回调。必须返回 `Result<Value, RpcError>`。系统会自动发送 Response。
-    /// * `on_notification`: 处理通知的回调。
-    pub fn init<FReq, FNotif>(on_request: FReq, on_notification: FNotif)
+    /// 初始化 RPC 系统，参数见 `RpcPeer::listen`，返回的监听线程在通道断开时结束
+    pub fn init<FReq, FNotif>(on_request: FReq, on_notification: FNotif) -> JoinHandle<()>
+    where
+        FReq: Fn(RpcRequest, RpcResponder) + Send + Sync + 'static,
+        FNotif: Fn(RpcNotification) + Send + Sync + 'static,
+    {
+        Self::init_with(Connection::stdio(), on_request, on_notification)
+    }
+
+    /// 使用 `Connection::open` 连接的通道初始化，参数与 `init` 相同
+    /// 通道带有令牌时，首先发送 `$/authenticate` 通知
+    pub fn init_with<FReq, FNotif>(
+        connection: Connection,
+        on_request: FReq,
+        on_notification: FNotif,
+    ) -> JoinHandle<()>
     where
-        FReq: Fn(RpcRequest) -> Result<Value, RpcError> + Send + Sync + 'static,
+        FReq: Fn(RpcRequest, RpcResponder) + Send + Sync + 'static,
         FNotif: Fn(RpcNotification) + Send + Sync + 'static,
     {
+        let Connection {
+            reader,
+            writer,
+            framing,
+            token,
+            ..
+        } = connection;
         let client = StdRpcClient {
-            next_id: AtomicU64::new(1),
-            pending_requests: Mutex::new(HashMap::new()),
+            peer: RpcPeer::new(reader, writer),
         };
+        client.set_framing(framing);
 
         if INSTANCE.set(client).is_err() {
             panic!("RpcClient already initialized");
         }
 
-        Self::start_listener(on_request, on_notification);
+        let client = Self::global();
+        if let Some(token) = token {
+            client.send_notification("$/authenticate", Some(serde_json::json!({ "token": token })));
+        }
+        client.listen(on_request, on_notification)
     }
+}
 
-    fn start_listener<FReq, FNotif>(on_request: FReq, on_notification: FNotif)
-    where
-        FReq: Fn(RpcRequest) -> Result<Value, RpcError> + Send + Sync + 'static,
-        FNotif: Fn(RpcNotification) + Send + Sync + 'static,
-    {
-        thread::spawn(move || {
-            let stdin = io::stdin();
-            let handle = stdin.lock();
-
-            for line in handle.lines() {
-                let line = match line {
-                    Ok(l) => l,
-                    Err(_) => break,
-                };
-                
-                let trimmed = line.trim();
-                if trimmed.is_empty() || !trimmed.starts_with('{') {
-                    continue;
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                match serde_json::from_str::<RawMessage>(trimmed) {
-                    Ok(raw) => {
-                        let client = Self::global();
-
-                        if let Some(method) = raw.method {
-                            // --- 有 Method: 是 请求 或 通知 ---
-                            if let Some(id) = raw.id {
-                                // A. 有 ID -> 请求 (Request)
-                                // 调用回调获取结果
-                                let req = RpcRequest { method, params: raw.params, id: id.clone() };
-                                let result = on_request(req);
-                                // 自动回复
-                                client.send_response(id, result);
-                            } else {
-                                // B. 无 ID -> 通知 (Notification)
-                                let notif = RpcNotification { method, params: raw.params };
-                                on_notification(notif);
-                            }
-                        } else if let Some(id) = raw.id {
-                            // --- 无 Method 有 ID: 是 响应 (Response) ---
-                            // 只有我们主动发起的请求才会收到这种消息
-                            // 注意：id 可能是 Number 可能是 String，转 String 查 Map
-                            let id_str = match &id {
-                                Value::String(s) => s.clone(),
-                                Value::Number(n) => n.to_string(),
-                                _ => id.to_string(),
-                            };
-
-                            let map = client.pending_requests.lock().unwrap();
-                            if let Some(tx) = map.get(&id_str) {
-                                let resp = RpcResponse {
-                                    jsonrpc: "2.0".to_string(),
-                                    result: raw.result,
-                                    error: raw.error,
-                                    id,
-                                };
-                                let _ = tx.send(resp);
-                            }
-                        } else {
-                            log::warn!("Unknown message format: {}", trimmed);
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Failed to parse JSON: {} | Content: {}", e, trimmed);
-                    }
-                }
-            }
-        });
+    #[test]
+    fn test_parse_message() {
+        let parse = |json: &str| parse_message(serde_json::from_str(json).unwrap());
+
+        let Ok(Message::Request(req)) =
+            parse(r#"{"jsonrpc":"2.0","method":"save","params":{},"id":"a"}"#)
+        else {
+            panic!("expected request");
+        };
+        assert_eq!((req.method.as_str(), req.id), ("save", Value::from("a")));
+        assert!(matches!(
+            parse(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+            Ok(Message::Notification(_))
+        ));
+        assert!(matches!(
+            parse(r#"{"jsonrpc":"2.0","result":1,"id":3}"#),
+            Ok(Message::Response(_))
+        ));
+
+        let invalid = [
+            (r#"{"method":"exit","id":1}"#, Value::from(1)),
+            (r#"{"jsonrpc":"1.0","method":"exit","id":1}"#, Value::from(1)),
+            (r#"{"jsonrpc":"2.0","method":1,"id":2}"#, Value::from(2)),
+            (r#"{"jsonrpc":"2.0","method":"exit","params":1,"id":3}"#, Value::from(3)),
+            (r#"{"jsonrpc":"2.0","method":"exit","id":{}}"#, Value::Null),
+            (r#"{"jsonrpc":"2.0"}"#, Value::Null),
+            ("1", Value::Null),
+        ];
+        for (json, expected_id) in invalid {
+            let Err((id, e)) = parse(json) else {
+                panic!("expected invalid request: {}", json);
+            };
+            assert_eq!(id, expected_id, "{}", json);
+            assert_eq!(e.code, RpcError::INVALID_REQUEST, "{}", json);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,125 @@
+use std::io::{self, BufRead, Write};
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Content-Length 允许的最大消息长度，避免错误的头部导致分配过大的内存
+const MAX_CONTENT_LENGTH: usize = 256 << 20;
+
+/// 发送消息的分帧方式，读取时自动识别两种格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Framing {
+    /// 每行一个 JSON
+    #[default]
+    Line,
+    /// 与 LSP 相同的 `Content-Length: N\r\n\r\n` 头部，消息可以包含换行
+    ContentLength,
+}
+
+/// 读取一条消息，跳过空行，EOF 时返回 None
+/// 以 `{` 或 `[` 开头的行按整行读取，`Content-Length:` 等头部按长度读取消息体
+/// 头部不合法时返回 InvalidData，此时已经读完头部，可以继续读取下一条消息
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with(['{', '[']) || header(trimmed).is_none() {
+            return Ok(Some(trimmed.to_string()));
+        }
+        break;
+    }
+
+    // 头部直到空行结束，只使用 Content-Length，其他头部(如 Content-Type)忽略
+    let mut length = None;
+    loop {
+        if let Some((name, value)) = header(line.trim())
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.parse::<usize>().ok();
+        }
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "eof in message header",
+            ));
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+    let length = match length {
+        Some(length) if length <= MAX_CONTENT_LENGTH => length,
+        Some(length) => return Err(invalid_data(format!("content too large: {}", length))),
+        None => return Err(invalid_data("missing or invalid Content-Length")),
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| invalid_data(e.to_string()))
+}
+
+/// 按分帧方式写出一条消息并 flush
+pub fn write_message(writer: &mut dyn Write, json: &str, framing: Framing) -> io::Result<()> {
+    match framing {
+        Framing::Line => {
+            writer.write_all(json.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        Framing::ContentLength => {
+            write!(writer, "Content-Length: {}\r\n\r\n", json.len())?;
+            writer.write_all(json.as_bytes())?;
+        }
+    }
+    writer.flush()
+}
+
+/// 只识别 Content-Length 和 Content-Type，避免把不合法的 JSON 行当作头部
+fn header(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.split_once(':')?;
+    let name = name.trim();
+    (name.eq_ignore_ascii_case("content-length") || name.eq_ignore_ascii_case("content-type"))
+        .then(|| (name, value.trim()))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_framing() {
+        let body = "{\n  \"id\": 1\n}";
+        let mut output = vec![];
+        write_message(&mut output, "{\"id\":0}", Framing::Line).unwrap();
+        write_message(&mut output, body, Framing::ContentLength).unwrap();
+        output.extend_from_slice(
+            b"\r\nContent-Type: application/json\r\ncontent-length: 2\r\n\r\n[]",
+        );
+        output.extend_from_slice(b"Content-Length: x\r\n\r\n{\"id\":2}\n");
+
+        let mut reader = Cursor::new(output);
+        let mut read = || read_message(&mut reader);
+        assert_eq!(read().unwrap().as_deref(), Some("{\"id\":0}"));
+        assert_eq!(read().unwrap().as_deref(), Some(body));
+        assert_eq!(read().unwrap().as_deref(), Some("[]"));
+        assert_eq!(read().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read().unwrap().as_deref(), Some("{\"id\":2}"));
+        assert_eq!(read().unwrap(), None);
+    }
+}
@@ -1,3 +1,14 @@
+pub mod base64;
 pub mod client;
+pub mod framing;
+pub mod peer;
+pub mod registry;
+pub mod transport;
+pub mod writer;
 
-pub use client::StdRpcClient;
\ No newline at end of file
+pub use client::{RpcResponder, StdRpcClient};
+pub use registry::{NoParams, RpcRegistry, TypedResponder};
+pub use framing::Framing;
+pub use peer::{RpcCallError, RpcPeer};
+pub use transport::Connection;
+pub use writer::WriterStats;
\ No newline at end of file
@@ -0,0 +1,515 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::stdio::{
+    client::{
+        Batch, InflightRequest, Message, ReplySink, RpcError, RpcNotification, RpcRequest,
+        RpcResponder, parse_message,
+    },
+    framing::{Framing, read_message},
+    writer::{Writer, WriterStats},
+};
+
+/// 双向取消请求的通知，参数为 `{id}`
+pub const CANCEL_METHOD: &str = "$/cancelRequest";
+
+/// 向对端发出的请求失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcCallError {
+    /// 超时未收到回复，已向对端发送 `$/cancelRequest`
+    Timeout,
+    /// 调用方通过 `RpcPeer::cancel` 取消
+    Cancelled,
+    /// 连接已经断开，无法发送或收到回复
+    Disconnected,
+    /// 对端回复的错误
+    Remote(RpcError),
+}
+
+impl fmt::Display for RpcCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcCallError::Timeout => f.write_str("request timed out"),
+            RpcCallError::Cancelled => f.write_str("request cancelled"),
+            RpcCallError::Disconnected => f.write_str("connection closed"),
+            RpcCallError::Remote(e) => write!(f, "rpc error {}: {}", e.code, e.message),
+        }
+    }
+}
+
+impl std::error::Error for RpcCallError {}
+
+type CallResult = Result<Value, RpcCallError>;
+
+/// 写出消息的一端，RpcResponder 和批量回复通过它回复，不需要知道写入端的类型
+pub(crate) trait Outgoing: Send + Sync {
+    fn write_raw(&self, msg: Value);
+    /// 请求已经回复，不再接受取消
+    fn finish(&self, request: &InflightRequest);
+}
+
+impl fmt::Debug for dyn Outgoing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Outgoing")
+    }
+}
+
+/// 监听线程和调用方共享的状态
+struct Shared {
+    next_id: AtomicU64,
+    // 存储发出的请求，等待回复: Map<ID, Sender>
+    pending_requests: Mutex<HashMap<String, mpsc::Sender<CallResult>>>,
+    // 对端发来、还没有回复的请求，用于处理对端的 $/cancelRequest
+    inflight: Mutex<HashMap<String, Arc<InflightRequest>>>,
+    // 监听线程读到 EOF 后不再发送请求
+    closed: AtomicBool,
+    // 宿主在 initialize 中选择接收的通知，None 为全部发送
+    notification_filter: Mutex<Option<HashSet<String>>>,
+    // 写线程的发送队列，每条消息整体写入
+    writer: Writer,
+    // 发送消息的分帧方式，可以在 initialize 中切换
+    framing: Mutex<Framing>,
+}
+
+impl Outgoing for Shared {
+    /// 入队时确定分帧方式，切换前入队的消息仍使用原来的方式
+    fn write_raw(&self, msg: Value) {
+        let framing = *self.framing.lock().unwrap();
+        self.writer.send(msg, framing);
+    }
+
+    fn finish(&self, request: &InflightRequest) {
+        self.inflight.lock().unwrap().remove(&request.key());
+    }
+}
+
+impl Shared {
+    /// 发送请求并注册等待回复，连接已断开时直接返回错误
+    fn start_call(&self, method: &str, params: Option<Value>) -> (u64, mpsc::Receiver<CallResult>) {
+        let id_num = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        if self.closed.load(Ordering::SeqCst) {
+            let _ = tx.send(Err(RpcCallError::Disconnected));
+            return (id_num, rx);
+        }
+
+        // 注册 Pending，统一转为 String 处理 ID 比较方便
+        self.pending_requests
+            .lock()
+            .unwrap()
+            .insert(id_num.to_string(), tx);
+
+        // 发送数据
+        let msg = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": id_num
+        });
+        self.write_raw(msg);
+        (id_num, rx)
+    }
+
+    /// 等待回复，超时后移除 Pending 并通知对端取消
+    fn wait(&self, id: u64, rx: mpsc::Receiver<CallResult>, timeout: Duration) -> CallResult {
+        match rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if self.cancel(id).is_some() {
+                    return Err(RpcCallError::Timeout);
+                }
+                // 超时的同时收到了回复或被取消
+                rx.try_recv().unwrap_or(Err(RpcCallError::Timeout))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(RpcCallError::Disconnected),
+        }
+    }
+
+    /// 移除还在等待的请求并通知对端，请求已经结束时返回 None
+    fn cancel(&self, id: u64) -> Option<mpsc::Sender<CallResult>> {
+        let tx = self
+            .pending_requests
+            .lock()
+            .unwrap()
+            .remove(&id.to_string())?;
+        // $/ 开头的协议通知不受通知过滤影响
+        self.write_raw(serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": CANCEL_METHOD,
+            "params": { "id": id }
+        }));
+        Some(tx)
+    }
+}
+
+/// 一个 JSON-RPC 连接的两端，从 R 读取请求和回复，消息由写线程写出
+/// StdRpcClient 是进程中使用 stdio 或 --rpc 通道的全局实例，嵌入方和测试可以创建自己的 RpcPeer
+pub struct RpcPeer<R> {
+    // listen 时移动到监听线程
+    reader: Mutex<Option<R>>,
+    shared: Arc<Shared>,
+}
+
+impl<R> RpcPeer<R>
+where
+    R: BufRead + Send + 'static,
+{
+    pub fn new(reader: R, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            reader: Mutex::new(Some(reader)),
+            shared: Arc::new(Shared {
+                next_id: AtomicU64::new(1),
+                pending_requests: Mutex::new(HashMap::new()),
+                inflight: Mutex::new(HashMap::new()),
+                closed: AtomicBool::new(false),
+                notification_filter: Mutex::new(None),
+                writer: Writer::spawn(writer),
+                framing: Mutex::new(Framing::default()),
+            }),
+        }
+    }
+
+    /// 只发送指定的通知，None 为全部发送
+    pub fn set_notification_filter(&self, methods: Option<HashSet<String>>) {
+        *self.shared.notification_filter.lock().unwrap() = methods;
+    }
+
+    /// 切换发送消息的分帧方式，读取时总是自动识别
+    pub fn set_framing(&self, framing: Framing) {
+        *self.shared.framing.lock().unwrap() = framing;
+    }
+
+    pub fn send_notification(&self, method: &str, params: Option<Value>) {
+        if let Some(methods) = &*self.shared.notification_filter.lock().unwrap()
+            && !methods.contains(method)
+        {
+            return;
+        }
+        // 构建原始 JSON 对象发送，避免定义冗余的 OutgoingNotification 结构
+        let msg = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params
+        });
+        self.shared.write_raw(msg);
+    }
+
+    /// 向对端发送请求并阻塞等待回复，需要先调用 listen 才能收到回复
+    /// 会阻塞调用线程，事件循环中应使用 call_async
+    pub fn call(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        timeout: Duration,
+    ) -> Result<Value, RpcCallError> {
+        let (id, rx) = self.shared.start_call(method, params);
+        self.shared.wait(id, rx, timeout)
+    }
+
+    /// 发送请求后立即返回请求 id，结果在其他线程中传给 callback，可以通过 cancel 取消
+    pub fn call_async<F>(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        timeout: Duration,
+        callback: F,
+    ) -> u64
+    where
+        F: FnOnce(Result<Value, RpcCallError>) + Send + 'static,
+    {
+        let (id, rx) = self.shared.start_call(method, params);
+        let shared = Arc::clone(&self.shared);
+        thread::spawn(move || callback(shared.wait(id, rx, timeout)));
+        id
+    }
+
+    /// 取消 call_async 发出的请求，向对端发送 `$/cancelRequest`，callback 收到 Cancelled
+    /// 请求已经结束时返回 false
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.shared.cancel(id) {
+            Some(tx) => {
+                let _ = tx.send(Err(RpcCallError::Cancelled));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 等待已经发出的消息全部写出，用于退出进程之前，宿主不再读取导致超时时返回 false
+    pub fn flush(&self, timeout: Duration) -> bool {
+        self.shared.writer.flush(timeout)
+    }
+
+    /// 发送队列的统计
+    pub fn writer_stats(&self) -> WriterStats {
+        self.shared.writer.stats()
+    }
+
+    /// 启动监听线程，读到 EOF 或读取失败时线程结束，只能调用一次
+    ///
+    /// # 参数
+    /// * `on_request`: 处理请求的回调。在监听线程中调用，不能阻塞，处理完成后通过 `RpcResponder` 回复，可以在其他线程中回复。
+    /// * `on_notification`: 处理通知的回调。
+    pub fn listen<FReq, FNotif>(&self, on_request: FReq, on_notification: FNotif) -> JoinHandle<()>
+    where
+        FReq: Fn(RpcRequest, RpcResponder) + Send + Sync + 'static,
+        FNotif: Fn(RpcNotification) + Send + Sync + 'static,
+    {
+        let mut reader = self
+            .reader
+            .lock()
+            .unwrap()
+            .take()
+            .expect("RpcPeer already listening");
+        let shared = Arc::clone(&self.shared);
+        thread::spawn(move || {
+            loop {
+                let message = match read_message(&mut reader) {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    // 头部不合法，消息体无法读取
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        log::error!("Invalid message header: {}", e);
+                        send_response(
+                            &shared,
+                            Value::Null,
+                            Err(RpcError::parse_error(e.to_string())),
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to read rpc message: {}", e);
+                        break;
+                    }
+                };
+
+                match serde_json::from_str::<Value>(&message) {
+                    Ok(Value::Array(items)) if items.is_empty() => {
+                        send_response(
+                            &shared,
+                            Value::Null,
+                            Err(RpcError::invalid_request("empty batch")),
+                        );
+                    }
+                    Ok(Value::Array(items)) => {
+                        handle_batch(&shared, items, &on_request, &on_notification);
+                    }
+                    Ok(value) => {
+                        handle_message(
+                            &shared,
+                            parse_message(value),
+                            &ReplySink::Single,
+                            &on_request,
+                            &on_notification,
+                        );
+                    }
+                    Err(e) => {
+                        log::error!("Failed to parse JSON: {} | Content: {}", e, message);
+                        send_response(
+                            &shared,
+                            Value::Null,
+                            Err(RpcError::parse_error(e.to_string())),
+                        );
+                    }
+                }
+            }
+            // 连接断开，还在等待回复的请求不会再收到回复
+            shared.closed.store(true, Ordering::SeqCst);
+            for (_, tx) in shared.pending_requests.lock().unwrap().drain() {
+                let _ = tx.send(Err(RpcCallError::Disconnected));
+            }
+        })
+    }
+}
+
+/// 不对应任何请求的错误回复
+fn send_response(shared: &Shared, id: Value, result: Result<Value, RpcError>) {
+    InflightRequest::new(id, ReplySink::Single).reply(shared, result);
+}
+
+/// 批量请求：所有请求的回复收集后作为一个数组回复，全部是通知时不回复
+fn handle_batch<FReq, FNotif>(
+    shared: &Arc<Shared>,
+    items: Vec<Value>,
+    on_request: &FReq,
+    on_notification: &FNotif,
+) where
+    FReq: Fn(RpcRequest, RpcResponder),
+    FNotif: Fn(RpcNotification),
+{
+    let messages = items.into_iter().map(parse_message).collect::<Vec<_>>();
+    let expected = messages
+        .iter()
+        .filter(|message| matches!(message, Ok(Message::Request(_)) | Err(_)))
+        .count();
+    let sink = if expected == 0 {
+        ReplySink::Single
+    } else {
+        ReplySink::Batch(Arc::new(Batch::new(expected, shared.clone())))
+    };
+    for message in messages {
+        handle_message(shared, message, &sink, on_request, on_notification);
+    }
+}
+
+fn handle_message<FReq, FNotif>(
+    shared: &Arc<Shared>,
+    message: Result<Message, (Value, RpcError)>,
+    sink: &ReplySink,
+    on_request: &FReq,
+    on_notification: &FNotif,
+) where
+    FReq: Fn(RpcRequest, RpcResponder),
+    FNotif: Fn(RpcNotification),
+{
+    match message {
+        // 由回调通过 RpcResponder 回复，不阻塞后续的请求
+        Ok(Message::Request(req)) => {
+            let request = Arc::new(InflightRequest::new(req.id.clone(), sink.clone()));
+            shared
+                .inflight
+                .lock()
+                .unwrap()
+                .insert(request.key(), Arc::clone(&request));
+            on_request(req, RpcResponder::new(request, shared.clone()));
+        }
+        // 对端取消还在处理的请求，立即回复 -32800，之后 RpcResponder 的回复被忽略
+        Ok(Message::Notification(notif)) if notif.method == CANCEL_METHOD => {
+            let id = notif.params.as_ref().and_then(|params| params.get("id"));
+            let request = id.and_then(|id| shared.inflight.lock().unwrap().remove(&id.to_string()));
+            match request {
+                Some(request) => request.reply(&**shared, Err(RpcError::request_cancelled())),
+                None => log::warn!("Cancel without inflight request: {:?}", id),
+            }
+        }
+        Ok(Message::Notification(notif)) => on_notification(notif),
+        Ok(Message::Response(resp)) => {
+            // 注意：id 可能是 Number 可能是 String，转 String 查 Map
+            let id_str = match &resp.id {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                id => id.to_string(),
+            };
+            let tx = shared.pending_requests.lock().unwrap().remove(&id_str);
+            match tx {
+                Some(tx) => {
+                    let result = match resp.error {
+                        Some(e) => Err(RpcCallError::Remote(e)),
+                        None => Ok(resp.result.unwrap_or(Value::Null)), // 成功但结果为 null
+                    };
+                    let _ = tx.send(result);
+                }
+                None => log::warn!("Response without pending request: {}", id_str),
+            }
+        }
+        Err((id, e)) => {
+            log::error!("Invalid request: {}", e.message);
+            InflightRequest::new(id, sink.clone()).reply(&**shared, Err(e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, PipeReader};
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_peer() {
+        let (peer_reader, mut host_writer) = io::pipe().unwrap();
+        let (host_reader, peer_writer) = io::pipe().unwrap();
+        let peer: Arc<RpcPeer<BufReader<PipeReader>>> =
+            Arc::new(RpcPeer::new(BufReader::new(peer_reader), peer_writer));
+        // slow 请求保存起来稍后回复
+        let slow = Arc::new(Mutex::new(vec![]));
+        let slow_for_listener = Arc::clone(&slow);
+        let listener = peer.listen(
+            move |req, responder| match req.method.as_str() {
+                "slow" => slow_for_listener.lock().unwrap().push(responder),
+                _ => responder.respond(Ok(req.params.unwrap_or_default())),
+            },
+            |_| {},
+        );
+        let mut host_reader = BufReader::new(host_reader);
+        let mut read = || {
+            let message = read_message(&mut host_reader).unwrap().unwrap();
+            serde_json::from_str::<Value>(&message).unwrap()
+        };
+        let mut send = |msg: Value| writeln!(host_writer, "{}", msg).unwrap();
+
+        send(json!({"jsonrpc": "2.0", "id": 1, "method": "echo", "params": [1]}));
+        assert_eq!(read(), json!({"jsonrpc": "2.0", "id": 1, "result": [1]}));
+
+        // 两个请求按相反的顺序回复，结果按 id 对应到各自的调用
+        let call = |method: &'static str| {
+            let peer = Arc::clone(&peer);
+            thread::spawn(move || peer.call(method, None, Duration::from_secs(5)))
+        };
+        let first = call("first");
+        let first_id = read()["id"].clone();
+        let second = call("second");
+        let second_id = read()["id"].clone();
+        send(json!({"jsonrpc": "2.0", "id": second_id, "error": {"code": 1, "message": "no"}}));
+        send(json!({"jsonrpc": "2.0", "id": first_id, "result": "first"}));
+        assert_eq!(first.join().unwrap(), Ok(Value::from("first")));
+        assert_eq!(
+            second.join().unwrap(),
+            Err(RpcCallError::Remote(RpcError::new(1, "no")))
+        );
+
+        // 超时后通知对端取消
+        let result = peer.call("timeout", None, Duration::from_millis(50));
+        assert_eq!(result, Err(RpcCallError::Timeout));
+        let id = read()["id"].clone();
+        assert_eq!(read()["params"], json!({ "id": id }));
+        assert!(peer.shared.pending_requests.lock().unwrap().is_empty());
+
+        // 异步调用被调用方取消
+        let (tx, rx) = mpsc::channel();
+        let id = peer.call_async("async", None, Duration::from_secs(5), move |result| {
+            tx.send(result).unwrap()
+        });
+        assert_eq!(read()["method"], "async");
+        assert!(peer.cancel(id));
+        assert!(!peer.cancel(id));
+        assert_eq!(rx.recv().unwrap(), Err(RpcCallError::Cancelled));
+        assert_eq!(
+            read(),
+            json!({"jsonrpc": "2.0", "method": CANCEL_METHOD, "params": { "id": id }})
+        );
+
+        // 对端取消还在处理的请求，之后的回复被忽略
+        send(json!({"jsonrpc": "2.0", "id": "s", "method": "slow"}));
+        send(json!({"jsonrpc": "2.0", "method": CANCEL_METHOD, "params": { "id": "s" }}));
+        assert_eq!(read()["error"]["code"], RpcError::REQUEST_CANCELLED);
+        let responder = slow.lock().unwrap().pop().unwrap();
+        assert!(responder.is_cancelled());
+        responder.respond(Ok(Value::Null));
+        send(json!({"jsonrpc": "2.0", "id": 2, "method": "echo"}));
+        assert_eq!(read()["id"], 2);
+
+        // 连接断开后等待中的和新的请求都失败
+        let (tx, rx) = mpsc::channel();
+        peer.call_async("pending", None, Duration::from_secs(5), move |result| {
+            tx.send(result).unwrap()
+        });
+        assert_eq!(read()["method"], "pending");
+        drop(host_writer);
+        listener.join().unwrap();
+        assert_eq!(rx.recv().unwrap(), Err(RpcCallError::Disconnected));
+        assert_eq!(
+            peer.call("closed", None, Duration::from_secs(5)),
+            Err(RpcCallError::Disconnected)
+        );
+    }
+}
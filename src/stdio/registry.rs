@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use schemars::{JsonSchema, Schema, SchemaGenerator, generate::SchemaSettings};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value, json};
+
+use crate::stdio::client::{RpcError, RpcRequest, RpcResponder};
+
+/// OpenRPC 的服务发现方法，返回 `RpcRegistry::discover` 的文档
+pub const DISCOVER_METHOD: &str = "rpc.discover";
+
+/// 没有参数的方法使用，接受省略的 params、`{}` 和 `[]`
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct NoParams {}
+
+/// 带结果类型的回复句柄，结果序列化后通过 RpcResponder 回复
+#[derive(Debug)]
+pub struct TypedResponder<R> {
+    inner: RpcResponder,
+    _result: PhantomData<fn(R)>,
+}
+
+impl<R: Serialize> TypedResponder<R> {
+    pub fn id(&self) -> &Value {
+        self.inner.id()
+    }
+
+    pub fn respond(self, result: Result<R, RpcError>) {
+        let result = result.and_then(|value| {
+            serde_json::to_value(value).map_err(|e| RpcError::internal_error(e.to_string()))
+        });
+        self.inner.respond(result);
+    }
+}
+
+impl<R: Serialize + DeserializeOwned> TypedResponder<R> {
+    /// 回复其他地方(如 webview)产生的 JSON 结果，不符合结果类型时回复内部错误
+    pub fn respond_value(self, result: Result<Value, RpcError>) {
+        let result = result.and_then(|value| {
+            serde_json::from_value::<R>(value)
+                .map_err(|e| RpcError::internal_error(format!("invalid result: {}", e)))
+        });
+        self.respond(result);
+    }
+}
+
+type Handler = Box<dyn Fn(Option<Value>, RpcResponder) + Send + Sync>;
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+struct MethodEntry {
+    handler: Handler,
+    params: SchemaFn,
+    result: SchemaFn,
+}
+
+/// 以 serde 类型注册的方法表，自动校验参数，并生成 OpenRPC 文档供宿主生成代码
+/// 处理函数在 stdio 的监听线程中调用，耗时的操作应转交其他线程后通过 TypedResponder 回复
+#[derive(Default)]
+pub struct RpcRegistry {
+    methods: BTreeMap<String, MethodEntry>,
+    /// 发送给宿主的通知，只用于生成文档
+    notifications: BTreeMap<String, SchemaFn>,
+}
+
+impl RpcRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册方法，参数无法解析为 `P` 时自动回复 -32602
+    pub fn method<P, R>(
+        &mut self,
+        name: &str,
+        handler: impl Fn(P, TypedResponder<R>) + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        P: DeserializeOwned + JsonSchema,
+        R: Serialize + JsonSchema,
+    {
+        let handler =
+            move |params: Option<Value>, responder: RpcResponder| match parse_params::<P>(params) {
+                Ok(params) => handler(
+                    params,
+                    TypedResponder {
+                        inner: responder,
+                        _result: PhantomData,
+                    },
+                ),
+                Err(e) => responder.respond(Err(e)),
+            };
+        self.methods.insert(
+            name.to_string(),
+            MethodEntry {
+                handler: Box::new(handler),
+                params: |generator| generator.root_schema_for::<P>(),
+                result: |generator| generator.subschema_for::<R>(),
+            },
+        );
+        self
+    }
+
+    /// 声明发送给宿主的通知及其参数类型
+    pub fn notification<P: JsonSchema>(&mut self, name: &str) -> &mut Self {
+        self.notifications.insert(name.to_string(), |generator| {
+            generator.root_schema_for::<P>()
+        });
+        self
+    }
+
+    /// 所有方法名，包括 rpc.discover
+    pub fn method_names(&self) -> Vec<String> {
+        std::iter::once(DISCOVER_METHOD.to_string())
+            .chain(self.methods.keys().cloned())
+            .collect()
+    }
+
+    pub fn notification_names(&self) -> Vec<String> {
+        self.notifications.keys().cloned().collect()
+    }
+
+    /// 作为 StdRpcClient::init 的请求回调
+    pub fn handle(&self, req: RpcRequest, responder: RpcResponder) {
+        if req.method == DISCOVER_METHOD {
+            responder.respond(Ok(self.discover()));
+            return;
+        }
+        match self.methods.get(&req.method) {
+            Some(entry) => (entry.handler)(req.params, responder),
+            None => responder.respond(Err(RpcError::method_not_found(&req.method))),
+        }
+    }
+
+    /// 所有方法和通知的 OpenRPC 文档，通知放在 `x-notifications` 中
+    pub fn discover(&self) -> Value {
+        let mut generator = SchemaSettings::draft07()
+            .with(|settings| {
+                settings.definitions_path = "/components/schemas".into();
+                settings.meta_schema = None;
+            })
+            .into_generator();
+        let methods = self
+            .methods
+            .iter()
+            .map(|(name, entry)| {
+                let result = (entry.result)(&mut generator);
+                json!({
+                    "name": name,
+                    "paramStructure": "by-name",
+                    "params": content_descriptors((entry.params)(&mut generator)),
+                    "result": {
+                        "name": format!("{}Result", name),
+                        "schema": result,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        let notifications = self
+            .notifications
+            .iter()
+            .map(|(name, params)| {
+                json!({
+                    "name": name,
+                    "params": content_descriptors(params(&mut generator)),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "openrpc": "1.2.6",
+            "info": {
+                "title": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "methods": methods,
+            "x-notifications": notifications,
+            "components": {
+                "schemas": generator.take_definitions(true),
+            },
+        })
+    }
+}
+
+/// 省略的 params 按没有字段的对象解析，使 NoParams 和全部字段可选的类型都能接受
+fn parse_params<P: DeserializeOwned>(params: Option<Value>) -> Result<P, RpcError> {
+    serde_json::from_value(params.unwrap_or_else(|| Value::Object(Map::new())))
+        .map_err(|e| RpcError::invalid_params(format!("invalid params: {}", e)))
+}
+
+/// 将参数类型的根 schema 按字段拆分为 OpenRPC 的 ContentDescriptor
+fn content_descriptors(schema: Schema) -> Vec<Value> {
+    let required = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return vec![];
+    };
+    properties
+        .iter()
+        .map(|(name, schema)| {
+            json!({
+                "name": name,
+                "required": required.contains(&Value::from(name.as_str())),
+                "schema": schema,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct CaptureParams {
+        monitor: u32,
+        format: Option<String>,
+    }
+
+    #[derive(Serialize, JsonSchema)]
+    struct CaptureResult {
+        path: String,
+    }
+
+    #[test]
+    fn test_registry() {
+        let params = parse_params::<CaptureParams>(Some(json!({"monitor": 1}))).unwrap();
+        assert_eq!((params.monitor, params.format), (1, None));
+        let params = parse_params::<CaptureParams>(Some(json!([2, "png"]))).unwrap();
+        assert_eq!(params.format.as_deref(), Some("png"));
+        let e = parse_params::<CaptureParams>(Some(json!({"monitor": "x"}))).unwrap_err();
+        assert_eq!(e.code, RpcError::INVALID_PARAMS);
+        assert!(parse_params::<NoParams>(None).is_ok());
+        assert!(parse_params::<NoParams>(Some(json!([]))).is_ok());
+
+        let mut registry = RpcRegistry::new();
+        registry
+            .method::<CaptureParams, CaptureResult>("capture", |_, _| {})
+            .method::<NoParams, ()>("exit", |_, _| {})
+            .notification::<CaptureResult>("captured");
+        let doc = registry.discover();
+        assert_eq!(doc["methods"][0]["name"], "capture");
+        assert_eq!(
+            doc["methods"][0]["params"],
+            json!([
+                {"name": "format", "required": false, "schema": {"type": ["string", "null"]}},
+                {"name": "monitor", "required": true, "schema": {"type": "integer", "format": "uint32", "minimum": 0}},
+            ])
+        );
+        assert_eq!(
+            doc["methods"][0]["result"]["schema"],
+            json!({"$ref": "#/components/schemas/CaptureResult"})
+        );
+        assert!(doc["components"]["schemas"]["CaptureResult"].is_object());
+        assert_eq!(doc["methods"][1]["params"], json!([]));
+        assert_eq!(doc["x-notifications"][0]["params"][0]["name"], "path");
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+
+use crate::{RpcTransport, stdio::framing::Framing};
+
+/// tcp 通道的认证令牌，通过环境变量传递，避免出现在进程参数中
+pub const TOKEN_ENV: &str = "QUICKCAP_RPC_TOKEN";
+
+/// 已连接的 JSON-RPC 通道，读写两端分别交给监听线程和 write_raw
+pub struct Connection {
+    pub(crate) reader: Box<dyn BufRead + Send>,
+    pub(crate) writer: Box<dyn Write + Send>,
+    pub(crate) framing: Framing,
+    /// 连接后首先通过 `$/authenticate` 通知发送给宿主
+    pub(crate) token: Option<String>,
+    /// 通道断开是否表示宿主已经退出
+    pub(crate) exit_on_close: bool,
+}
+
+impl Connection {
+    /// 标准输入输出，stdout 被其他输出占用时协议会被破坏
+    pub fn stdio() -> Self {
+        Self {
+            reader: Box::new(BufReader::new(io::stdin())),
+            writer: Box::new(io::stdout()),
+            framing: Framing::default(),
+            token: None,
+            exit_on_close: stdin_from_host(),
+        }
+    }
+
+    /// 使用任意的读写端，用于嵌入和测试
+    pub fn new(reader: impl BufRead + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            framing: Framing::default(),
+            token: None,
+            exit_on_close: true,
+        }
+    }
+
+    /// 发送消息使用的分帧方式
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// 连接宿主创建的套接字或管道，quickcap 作为客户端
+    pub fn open(transport: &RpcTransport) -> io::Result<Self> {
+        match transport {
+            RpcTransport::Stdio => Ok(Self::stdio()),
+            RpcTransport::Unix(path) => {
+                #[cfg(unix)]
+                {
+                    let stream = std::os::unix::net::UnixStream::connect(path)?;
+                    Ok(Self::new(BufReader::new(stream.try_clone()?), stream))
+                }
+                #[cfg(not(unix))]
+                {
+                    Err(unsupported(format!("unix socket {}", path.display())))
+                }
+            }
+            RpcTransport::Tcp(addr) => {
+                let token = std::env::var(TOKEN_ENV).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("tcp transport requires {}", TOKEN_ENV),
+                    )
+                })?;
+                let stream = std::net::TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                Ok(Self {
+                    token: Some(token),
+                    ..Self::new(BufReader::new(stream.try_clone()?), stream)
+                })
+            }
+            RpcTransport::Pipe(name) => {
+                #[cfg(windows)]
+                {
+                    let pipe = std::fs::OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .open(name)?;
+                    Ok(Self::new(BufReader::new(pipe.try_clone()?), pipe))
+                }
+                #[cfg(not(windows))]
+                {
+                    Err(unsupported(format!("named pipe {}", name)))
+                }
+            }
+            RpcTransport::Fd(fd) => {
+                let file = inherited_file(*fd)?;
+                Ok(Self::new(BufReader::new(file.try_clone()?), file))
+            }
+        }
+    }
+}
+
+/// stdin 是宿主创建的管道、套接字或文件时，EOF 表示宿主已经退出
+/// 终端、/dev/null 和没有 stdin(从桌面启动)时不会有宿主，EOF 不退出
+fn stdin_from_host() -> bool {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::{fd::AsFd, unix::fs::FileTypeExt};
+        stdin
+            .as_fd()
+            .try_clone_to_owned()
+            .and_then(|fd| std::fs::File::from(fd).metadata())
+            .is_ok_and(|metadata| !metadata.file_type().is_char_device())
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use windows::Win32::{
+            Foundation::HANDLE,
+            Storage::FileSystem::{FILE_TYPE_DISK, FILE_TYPE_PIPE, GetFileType},
+        };
+        // NUL 和控制台是字符设备，没有 stdin 时为 FILE_TYPE_UNKNOWN
+        let file_type = unsafe { GetFileType(HANDLE(stdin.as_raw_handle())) };
+        file_type == FILE_TYPE_PIPE || file_type == FILE_TYPE_DISK
+    }
+}
+
+/// 父进程传入的双向描述符(如 socketpair)，Windows 上为可继承的句柄
+#[cfg(unix)]
+fn inherited_file(fd: i32) -> io::Result<std::fs::File> {
+    use std::os::fd::FromRawFd;
+    // 0-2 是标准输入输出，由 Connection::stdio 处理
+    if fd <= 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid rpc fd {}", fd),
+        ));
+    }
+    // SAFETY: 描述符由父进程传入，进程中没有其他地方使用
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(windows)]
+fn inherited_file(handle: i32) -> io::Result<std::fs::File> {
+    use std::os::windows::io::{FromRawHandle, RawHandle};
+    if handle <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid rpc handle {}", handle),
+        ));
+    }
+    // SAFETY: 句柄由父进程继承，进程中没有其他地方使用
+    Ok(unsafe { std::fs::File::from_raw_handle(handle as isize as RawHandle) })
+}
+
+fn unsupported(what: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} is not supported on this platform", what),
+    )
+}
@@ -0,0 +1,268 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::stdio::framing::{Framing, write_message};
+
+/// 发送队列最多缓存的消息数，队列满时丢弃新的消息，发送方不等待写线程
+const QUEUE_CAPACITY: usize = 1024;
+/// 队列中超过该数量时丢弃新的通知，剩余的空间留给请求和回复
+const NOTIFICATION_LIMIT: usize = QUEUE_CAPACITY * 3 / 4;
+
+enum Item {
+    Message(Value, Framing),
+    /// 之前入队的消息都写出后回复
+    Flush(mpsc::Sender<()>),
+}
+
+/// 发送队列的统计，用于发现读取缓慢的宿主
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct WriterStats {
+    /// 已入队还没有写出的消息数
+    pub queued: usize,
+    /// 队列中同时等待的最多消息数
+    pub max_queued: usize,
+    /// 已写出的消息数
+    pub written: u64,
+    /// 已写出的 JSON 字节数，不包括分帧的头部和换行
+    pub bytes: u64,
+    /// 超过 NOTIFICATION_LIMIT 时丢弃的通知数
+    pub dropped: u64,
+    /// 队列已满时丢弃的请求、回复和协议通知数，对端不会再收到这些回复
+    pub overflowed: u64,
+    /// 写入失败的次数，失败的消息被丢弃
+    pub errors: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicUsize,
+    max_queued: AtomicUsize,
+    written: AtomicU64,
+    bytes: AtomicU64,
+    dropped: AtomicU64,
+    overflowed: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// 由单独的线程按入队顺序序列化并写出消息，宿主读取缓慢时不会阻塞界面和 webview 的回调
+pub(crate) struct Writer {
+    queue: mpsc::SyncSender<Item>,
+    counters: Arc<Counters>,
+}
+
+impl Writer {
+    /// 启动写线程，所有 Writer 被丢弃后线程结束
+    pub(crate) fn spawn(mut writer: impl Write + Send + 'static) -> Self {
+        let (queue, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let counters = Arc::new(Counters::default());
+        let thread_counters = Arc::clone(&counters);
+        thread::Builder::new()
+            .name("rpc-writer".to_string())
+            .spawn(move || {
+                let counters = thread_counters;
+                for item in rx {
+                    match item {
+                        Item::Message(msg, framing) => {
+                            let json = serde_json::to_string(&msg).unwrap();
+                            match write_message(&mut writer, &json, framing) {
+                                Ok(()) => {
+                                    counters.written.fetch_add(1, Ordering::Relaxed);
+                                    counters
+                                        .bytes
+                                        .fetch_add(json.len() as u64, Ordering::Relaxed);
+                                }
+                                // 宿主关闭通道后每条消息都会失败，只记录第一次
+                                Err(e) => {
+                                    if counters.errors.fetch_add(1, Ordering::Relaxed) == 0 {
+                                        log::error!("Failed to write rpc message: {}", e);
+                                    }
+                                }
+                            }
+                            counters.queued.fetch_sub(1, Ordering::Relaxed);
+                        }
+                        Item::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn rpc writer thread");
+        Self { queue, counters }
+    }
+
+    /// 消息入队，不会阻塞调用线程，界面线程和 webview 的回调中也可以发送
+    /// 宿主读取缓慢时先丢弃通知，为请求和回复保留空间，队列满时再丢弃请求和回复
+    pub(crate) fn send(&self, msg: Value, framing: Framing) {
+        let notification = is_notification(&msg);
+        if notification && self.counters.queued.load(Ordering::Relaxed) >= NOTIFICATION_LIMIT {
+            if self.counters.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                log::error!("rpc writer queue is full, the host is reading slowly");
+            }
+            return;
+        }
+        let queued = self.counters.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.queue.try_send(Item::Message(msg, framing)) {
+            Ok(()) => {
+                self.counters
+                    .max_queued
+                    .fetch_max(queued, Ordering::Relaxed);
+            }
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+                let counter = if notification {
+                    &self.counters.dropped
+                } else {
+                    &self.counters.overflowed
+                };
+                if counter.fetch_add(1, Ordering::Relaxed) == 0 {
+                    log::error!("rpc writer queue is full, dropping messages");
+                }
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 等待之前入队的消息全部写出，宿主不再读取导致超时时返回 false
+    pub(crate) fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let (tx, rx) = mpsc::channel();
+        let mut item = Item::Flush(tx);
+        // 队列满时不能阻塞等待，否则宿主不读取时无法退出
+        loop {
+            match self.queue.try_send(item) {
+                Ok(()) => break,
+                Err(mpsc::TrySendError::Full(back)) if Instant::now() < deadline => {
+                    item = back;
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(_) => return false,
+            }
+        }
+        rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .is_ok()
+    }
+
+    pub(crate) fn stats(&self) -> WriterStats {
+        let counters = &self.counters;
+        WriterStats {
+            queued: counters.queued.load(Ordering::Relaxed),
+            max_queued: counters.max_queued.load(Ordering::Relaxed),
+            written: counters.written.load(Ordering::Relaxed),
+            bytes: counters.bytes.load(Ordering::Relaxed),
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            overflowed: counters.overflowed.load(Ordering::Relaxed),
+            errors: counters.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 没有 id 的应用通知，`$/` 开头的协议通知(如取消请求)不丢弃
+fn is_notification(msg: &Value) -> bool {
+    msg.get("id").is_none()
+        && msg
+            .get("method")
+            .and_then(Value::as_str)
+            .is_some_and(|method| !method.starts_with("$/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Condvar, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// 打开之前所有写入都阻塞，模拟不读取的宿主
+    #[derive(Clone, Default)]
+    struct Gate {
+        opened: Arc<(Mutex<bool>, Condvar)>,
+        output: Arc<Mutex<Vec<u8>>>,
+        /// 写线程已经开始写入第一条消息
+        entered: Arc<AtomicBool>,
+    }
+
+    impl Gate {
+        fn open(&self) {
+            *self.opened.0.lock().unwrap() = true;
+            self.opened.1.notify_all();
+        }
+
+        fn output(&self) -> String {
+            String::from_utf8(self.output.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Gate {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.entered.store(true, Ordering::SeqCst);
+            let (lock, condvar) = &*self.opened;
+            drop(
+                condvar
+                    .wait_while(lock.lock().unwrap(), |opened| !*opened)
+                    .unwrap(),
+            );
+            self.output.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let gate = Gate::default();
+        let writer = Writer::spawn(gate.clone());
+        // 写线程取出第一条消息后阻塞，之后的消息留在队列中
+        writer.send(json!({ "method": "n", "params": 0 }), Framing::Line);
+        while !gate.entered.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        for i in 1..NOTIFICATION_LIMIT {
+            writer.send(json!({ "method": "n", "params": i }), Framing::Line);
+        }
+        // 通知超过限制后被丢弃，回复仍然可以使用剩余的空间
+        writer.send(json!({ "method": "dropped" }), Framing::Line);
+        let replies = QUEUE_CAPACITY - NOTIFICATION_LIMIT + 1;
+        for id in 0..replies {
+            writer.send(json!({ "id": id, "result": null }), Framing::Line);
+        }
+        // 队列已满，发送不等待写线程，回复也被丢弃
+        writer.send(json!({ "id": "overflow", "result": null }), Framing::Line);
+        let stats = writer.stats();
+        assert_eq!(stats.written, 0);
+        assert_eq!(stats.dropped, 1);
+        assert_eq!(stats.overflowed, 1);
+        assert_eq!(stats.queued, QUEUE_CAPACITY + 1);
+        assert!(!writer.flush(Duration::from_millis(50)));
+
+        gate.open();
+        assert!(writer.flush(Duration::from_secs(5)));
+        writer.send(json!("last"), Framing::ContentLength);
+        assert!(writer.flush(Duration::from_secs(5)));
+        let stats = writer.stats();
+        assert_eq!(stats.queued, 0);
+        assert_eq!(stats.written, QUEUE_CAPACITY as u64 + 2);
+        assert_eq!(stats.max_queued, QUEUE_CAPACITY + 1);
+        let output = gate.output();
+        assert!(output.starts_with("{\"method\":\"n\",\"params\":0}\n"));
+        assert!(!output.contains("dropped"));
+        assert!(!output.contains("overflow"));
+        assert!(output.ends_with(&format!(
+            "{{\"id\":{},\"result\":null}}\nContent-Length: 6\r\n\r\n\"last\"",
+            replies - 1
+        )));
+    }
+}
@@ -242,6 +242,10 @@ export class DrawScreen {
           })
           this.windows = arr
           this.editTools.setMonitors(monitors)
+          // fullscreen 模式打开后直接选中整个显示器，没有显示器信息时选中整个窗口
+          if (window.app.initialMode === 'fullscreen') {
+            this.selectRect(monitors[0] || arr[arr.length - 1])
+          }
         },
       )
       .catch((err: any) => console.error(err))
@@ -412,7 +416,8 @@ export class DrawScreen {
       return
     }
 
-    if (!this.isSelecting) {
+    // window 模式只能吸附窗口，拖拽时也不会框选区域
+    if (!this.isSelecting || window.app.initialMode === 'window') {
       const window = matchWindow({
         x: e.clientX,
         y: e.clientY,
@@ -458,6 +463,22 @@ export class DrawScreen {
     this.mode = 'waitEdit'
   }
 
+  /**
+   * 直接选中指定区域，进入等待编辑状态
+   */
+  private selectRect = (rect: {x: number; y: number; width: number; height: number}) => {
+    if (this.mode !== 'select') {
+      return
+    }
+    this.selectX = rect.x
+    this.selectY = rect.y
+    this.selectWidth = rect.width
+    this.selectHeight = rect.height
+    this.mode = 'waitEdit'
+    this.drawMask()
+    this.editTools.render(true, rect)
+  }
+
   private resizeStart = (e: MouseEvent) => {
     if (this.mode === 'select') {
       return
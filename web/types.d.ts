@@ -1,3 +1,10 @@
+interface Rect {
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+}
+
 interface App {
     exit: () => void;
     getImage: () => Promise<{
@@ -10,6 +17,12 @@ interface App {
     getWindows: () => Promise<any>;
     notify: (method: any, params?: {}) => void;
     isDebug: boolean;
+    initialMode: 'region' | 'window' | 'fullscreen';
+    theme: 'system' | 'light' | 'dark';
+    keymap: Partial<Record<'exit' | 'save' | 'undo', string>>;
+    desktopOrigin: { x: number; y: number } | null;
+    toCssRect: (rect: Rect) => Rect;
+    handleRpc: (id: number | string, method: string, params?: any) => Promise<void>;
 }
 
 declare global {
@@ -1,5 +1,4 @@
-import { DPR, editToolGap, editToolHeight } from "../const";
-import { isWindows } from "./device";
+import { editToolGap, editToolHeight } from "../const";
 
 let promise: Promise<{
   imageData: ImageData;
@@ -282,11 +281,13 @@ export const getRectForWindow = ({
   width: number;
   height: number;
 }) => {
-
-  const rx = isWindows() ? x / DPR : x;
-  const ry = isWindows() ? y / DPR : y;
-  const rwidth = isWindows() ? width / DPR : width;
-  const rheight = isWindows() ? height / DPR : height;
+  // 与 setSelection 使用相同的换算，虚拟桌面后端需要减去原点并除以 devicePixelRatio
+  const {
+    x: rx,
+    y: ry,
+    width: rwidth,
+    height: rheight,
+  } = window.app.toCssRect({ x, y, width, height });
 
   const maxX = window.innerWidth;
   const maxY = window.innerHeight;